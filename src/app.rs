@@ -4,9 +4,15 @@ use leptos::*;
 
 mod login;
 mod dashboard;
+mod constellation;
+mod share;
+mod history;
+
+pub use login::{Login, LoginInfo};
 
 use login::SpotifyButtons;
 use dashboard::Dashboard;
+use share::SharedSnapshot;
 
 use crate::errors::{AppError, ErrorTemplate};
 
@@ -27,6 +33,7 @@ pub fn App() -> impl IntoView {
                         <Route path="/" view=IndexPage />
                         <Route path="/about" view=AboutPage />
                         <Route path="/dashboard" view=Dashboard />
+                        <Route path="/s/:id" view=SharedSnapshot />
                     </Routes>
                 </main>
                 <footer class="footer footer-center p-4 bg-base-400 text-base-content">
@@ -0,0 +1,178 @@
+use std::collections::HashSet;
+
+use leptos::*;
+
+use crate::constellation::{
+    self,
+    genres,
+    layout::{self, star_brightness, star_radius, LayoutOptions},
+    ArtistNode,
+};
+
+/// Draws a [`constellation::Constellation`] as an inline SVG star map.
+///
+/// Stars are sized by popularity and get brighter the higher the artist
+/// ranks in the user's top artists. Hovering or clicking a star shows the
+/// artist's details below the map. Artists in `followed` (by ID) get a ring,
+/// and every genre cluster is drawn as a labelled region behind its stars.
+#[component]
+pub fn Constellation(graph: constellation::Constellation, #[prop(optional)] followed: HashSet<String>) -> impl IntoView {
+    let options = LayoutOptions::default();
+    let positions = layout::layout(&graph, &options);
+    let count = graph.nodes.len();
+
+    let selected = create_rw_signal::<Option<usize>>(None);
+
+    let regions = genres::clusters(&graph.nodes)
+        .into_iter()
+        .map(|cluster| {
+            let (center, radius) = layout::cluster_region(&cluster, &graph.nodes, &positions);
+
+            view! {
+                <g>
+                    <circle
+                        cx=center.x
+                        cy=center.y
+                        r=radius
+                        fill="white"
+                        fill-opacity=0.02 + 0.06 * cluster.weight
+                        stroke="white"
+                        stroke-opacity=0.15
+                        stroke-dasharray="2 8"
+                    />
+                    <text
+                        x=center.x
+                        y=(center.y - radius - 8.0).max(16.0)
+                        text-anchor="middle"
+                        fill="white"
+                        fill-opacity=0.6
+                        font-size="18"
+                        class="capitalize"
+                    >
+                        {cluster.name}
+                    </text>
+                </g>
+            }
+        })
+        .collect_view();
+
+    let edges = graph
+        .edges
+        .iter()
+        .map(|edge| {
+            let source = positions[edge.source];
+            let target = positions[edge.target];
+            let dash = layout::edge_dash(edge.kind)
+                .map(|(dash, gap)| format!("{dash} {gap}"))
+                .unwrap_or_default();
+
+            view! {
+                <line
+                    x1=source.x
+                    y1=source.y
+                    x2=target.x
+                    y2=target.y
+                    stroke="white"
+                    stroke-opacity=0.1 + 0.5 * edge.weight
+                    stroke-width=1.0 + 2.0 * edge.weight
+                    stroke-dasharray=dash
+                />
+            }
+        })
+        .collect_view();
+
+    let stars = graph
+        .nodes
+        .iter()
+        .zip(positions.iter())
+        .enumerate()
+        .map(|(index, (node, position))| {
+            let ring = followed.contains(&node.id).then(|| view! {
+                <circle
+                    cx=position.x
+                    cy=position.y
+                    r=star_radius(node) + 5.0
+                    fill="none"
+                    stroke="white"
+                    stroke-opacity=0.6
+                    pointer-events="none"
+                />
+            });
+
+            view! {
+                {ring}
+                <circle
+                    cx=position.x
+                    cy=position.y
+                    r=star_radius(node)
+                    fill="white"
+                    fill-opacity=star_brightness(node, count)
+                    class="cursor-pointer"
+                    class:stroke-accent=move || selected.get() == Some(index)
+                    stroke-width="3"
+                    on:mouseenter=move |_| selected.set(Some(index))
+                    on:click=move |_| selected.set(Some(index))
+                >
+                    <title>{node.name.clone()}</title>
+                </circle>
+            }
+        })
+        .collect_view();
+
+    let nodes = graph.nodes.clone();
+    let followed = store_value(followed);
+
+    view! {
+        <div class="flex flex-col items-center space-y-4">
+            <svg
+                viewBox=format!("0 0 {} {}", options.width, options.height)
+                class="w-full max-w-3xl rounded-xl bg-neutral shadow-xl"
+            >
+                <g>{regions}</g>
+                <g>{edges}</g>
+                <g>{stars}</g>
+            </svg>
+            {move || {
+                selected
+                    .get()
+                    .and_then(|index| nodes.get(index).cloned())
+                    .map(|node| {
+                        let following = followed.with_value(|followed| followed.contains(&node.id));
+                        view! { <ArtistCard node following /> }
+                    })
+            }}
+
+        </div>
+    }
+}
+
+/// Details for the selected star of a [`Constellation`].
+#[component]
+fn ArtistCard(node: ArtistNode, following: bool) -> impl IntoView {
+    view! {
+        <div class="card card-side w-full max-w-md bg-base-100 shadow-xl">
+            {node
+                .image
+                .map(|image| {
+                    view! {
+                        <figure class="w-24 shrink-0">
+                            <img src=image alt=node.name.clone()/>
+                        </figure>
+                    }
+                })}
+            <div class="card-body p-4">
+                <h2 class="card-title">
+                    {node.name}
+                    {following.then(|| view! { <span class="badge badge-accent">"Following"</span> })}
+                </h2>
+                <div class="flex flex-wrap gap-1">
+                    {node
+                        .genres
+                        .into_iter()
+                        .map(|genre| view! { <span class="badge badge-outline">{genre}</span> })
+                        .collect_view()}
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -1,20 +1,438 @@
+use std::collections::HashSet;
+
 use leptos::*;
-use rspotify::model::PrivateUser;
+use leptos_router::*;
+use rspotify::model::{FullArtist, PrivateUser, TimeRange};
+
+use super::{constellation::Constellation, history::RankMovement, share::ShareControls};
+use crate::{client, constellation, EXPORT_ENDPOINT, LOGOUT_ENDPOINT};
+
+/// The artists the user follows, shared by the views that mark them.
+type Followed = Resource<usize, Result<Option<Vec<FullArtist>>, ServerFnError>>;
+
+/// The CSV tables of the data export, see `export::data::Table`.
+const DATA_TABLES: [&str; 6] = ["profile", "artists", "genres", "edges", "tracks", "followed"];
 
-use crate::client;
+const RANGES: [TimeRange; 3] = [TimeRange::ShortTerm, TimeRange::MediumTerm, TimeRange::LongTerm];
 
 #[component]
 pub fn Dashboard() -> impl IntoView {
+    let query = use_query_map();
+
+    // the selected range lives in `?range=` so it survives reloads and can be shared
+    let range = create_memo(move |_| {
+        query.with(|query| {
+            query
+                .get("range")
+                .and_then(|range| client::range_from_query(range))
+                .unwrap_or(TimeRange::MediumTerm)
+        })
+    });
+
+    // every completed refresh bumps the version, refetching the views below
+    let refresh = create_server_action::<client::RefreshData>();
+    let version = refresh.version();
+
+    let followed: Followed = create_resource(move || version.get(), |_| async move {
+        client::get_followed_artists().await });
+
     view! {
-        <div class="grow p-4">
-            <User />
+        <div class="grow p-4 space-y-6">
+            <User version />
+            <RefreshButton refresh />
+            <RangeTabs range />
+            <StarMap range version followed />
+            <div class="flex flex-wrap justify-center gap-6">
+                <TopArtists range version followed />
+                <TopTracks range version />
+            </div>
+            <RankMovement range version />
+            <GenreBreakdown version />
+            <ShareControls range />
+            <AccountButtons />
         </div>
     }
 }
 
 #[component]
-pub fn User() -> impl IntoView {
-    let client = create_resource(|| (), |_| async move {
+pub fn AccountButtons() -> impl IntoView {
+    let delete = create_server_action::<client::DeleteMyData>();
+    let confirming = create_rw_signal(false);
+    let navigate = use_navigate();
+
+    create_effect(move |_| {
+        if let Some(Ok(())) = delete.value().get() {
+            navigate("/", Default::default());
+        }
+    });
+
+    view! {
+        <div class="flex flex-col items-center space-y-2">
+            <div class="space-x-2">
+                <form method="post" action=LOGOUT_ENDPOINT class="inline">
+                    <button type="submit" class="btn btn-sm">"Log Out"</button>
+                </form>
+                <div class="dropdown dropdown-top">
+                    <label tabindex="0" class="btn btn-sm">"Download My Data"</label>
+                    <ul tabindex="0" class="dropdown-content menu menu-sm z-10 w-48 rounded-box bg-base-200 p-2 shadow">
+                        <li>
+                            <a href=format!("{EXPORT_ENDPOINT}/data.json") rel="external" download>"Everything (JSON)"</a>
+                        </li>
+                        {DATA_TABLES
+                            .map(|table| view! {
+                                <li>
+                                    <a href=format!("{EXPORT_ENDPOINT}/{table}.csv") rel="external" download>
+                                        {format!("{} (CSV)", capitalize(table))}
+                                    </a>
+                                </li>
+                            })
+                            .collect_view()}
+                    </ul>
+                </div>
+                <button class="btn btn-sm btn-error btn-outline" on:click=move |_| confirming.set(true)>
+                    "Delete My Data"
+                </button>
+            </div>
+            <Show when=move || confirming.get()>
+                <div class="alert alert-warning w-fit">
+                    <span>"This logs you out and removes everything starify stores about you."</span>
+                    <div class="space-x-2">
+                        <button class="btn btn-sm" on:click=move |_| confirming.set(false)>
+                            "Cancel"
+                        </button>
+                        <button
+                            class="btn btn-sm btn-error"
+                            disabled=move || delete.pending().get()
+                            on:click=move |_| delete.dispatch(client::DeleteMyData {})
+                        >
+                            "Delete Everything"
+                        </button>
+                    </div>
+                </div>
+            </Show>
+            {move || match delete.value().get() {
+                Some(Err(err)) => Some(view! { <p class="text-xs text-error">{err.to_string()}</p> }),
+                _ => None,
+            }}
+
+        </div>
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+#[component]
+pub fn RefreshButton(refresh: Action<client::RefreshData, Result<Option<client::RefreshStatus>, ServerFnError>>) -> impl IntoView {
+    let status = create_resource(move || refresh.version().get(), |_| async move {
+        client::get_refresh_status().await });
+
+    let pending = refresh.pending();
+
+    view! {
+        <div class="flex flex-col items-center space-y-1">
+            <Suspense>
+                {move || {
+                    status
+                        .get()
+                        .and_then(Result::ok)
+                        .flatten()
+                        .map(|status| {
+                            view! {
+                                <button
+                                    class="btn btn-sm"
+                                    disabled=move || pending.get() || status.available_at.is_some()
+                                    on:click=move |_| refresh.dispatch(client::RefreshData {})
+                                >
+                                    "Refresh My Data"
+                                </button>
+                                <p class="text-xs">
+                                    {status
+                                        .refreshed_at
+                                        .map(|refreshed_at| format!("Last refreshed {}", format_timestamp(refreshed_at)))}
+                                </p>
+                            }
+                        })
+                }}
+
+            </Suspense>
+            {move || match refresh.value().get() {
+                Some(Err(err)) => Some(view! { <p class="text-xs text-error">{err.to_string()}</p> }),
+                _ => None,
+            }}
+
+        </div>
+    }
+}
+
+/// Format a unix timestamp as `YYYY-MM-DD HH:MM UTC`.
+fn format_timestamp(timestamp: i64) -> String {
+    match time::OffsetDateTime::from_unix_timestamp(timestamp) {
+        Ok(time) => format!("{} {:02}:{:02} UTC", time.date(), time.hour(), time.minute()),
+        Err(_) => "at an unknown time".to_string(),
+    }
+}
+
+#[component]
+pub fn RangeTabs(#[prop(into)] range: Signal<TimeRange>) -> impl IntoView {
+    view! {
+        <div role="tablist" class="tabs tabs-boxed mx-auto w-fit">
+            {RANGES
+                .into_iter()
+                .map(|tab| {
+                    view! {
+                        <A
+                            href=format!("?range={}", client::range_to_query(tab))
+                            class=move || if range.get() == tab { "tab tab-active" } else { "tab" }
+                        >
+                            {client::range_label(tab)}
+                        </A>
+                    }
+                })
+                .collect_view()}
+        </div>
+    }
+}
+
+#[component]
+pub fn StarMap(
+    #[prop(into)] range: Signal<TimeRange>,
+    #[prop(into)] version: Signal<usize>,
+    followed: Followed,
+) -> impl IntoView {
+    let graph = create_resource(move || (range.get(), version.get()), |(range, _)| async move {
+        constellation::get_constellation(range).await });
+
+    view! {
+        <Suspense fallback=move || view! { <div class="mx-auto w-full max-w-3xl aspect-square skeleton rounded-xl"></div> }>
+            {move || {
+                graph
+                    .get()
+                    .map(|graph| match graph {
+                        Ok(Some(graph)) => {
+                            let followed = followed_ids(&followed_artists(followed));
+                            let export = move |format: &str| {
+                                format!("{EXPORT_ENDPOINT}/constellation.{format}?range={}", client::range_to_query(range.get()))
+                            };
+
+                            view! {
+                                <Constellation graph followed />
+                                // rel="external" so the router lets the server handle them
+                                <div class="flex justify-center space-x-2">
+                                    <a href=export("png") rel="external" download class="btn btn-sm">"Download PNG"</a>
+                                    <a href=export("svg") rel="external" download class="btn btn-sm">"Download SVG"</a>
+                                </div>
+                            }.into_view()
+                        }
+                        Ok(None) => ().into_view(),
+                        Err(err) => view! { <p>"An Error " {err.to_string()}</p> }.into_view(),
+                    })
+            }}
+
+        </Suspense>
+    }
+}
+
+#[component]
+pub fn TopArtists(
+    #[prop(into)] range: Signal<TimeRange>,
+    #[prop(into)] version: Signal<usize>,
+    followed: Followed,
+) -> impl IntoView {
+    let artists = create_resource(move || (range.get(), version.get()), |(range, _)| async move {
+        client::get_top_artists(range).await });
+
+    let artist_row = |rank: usize, artist: FullArtist, following: bool| view! {
+        <li class="flex items-center space-x-3">
+            <span class="w-6 text-right font-mono">{rank + 1}</span>
+            <div class="avatar">
+                <div class="w-10 mask mask-squircle">
+                    {artist.images.first().map(|image| view! { <img src=image.url.clone()/> })}
+                </div>
+            </div>
+            <span class="font-bold">{artist.name}</span>
+            {following.then(|| view! { <span class="badge badge-accent badge-sm">"Following"</span> })}
+        </li>
+    };
+
+    view! {
+        <Suspense fallback=move || view! { <div class="w-full max-w-md h-64 skeleton rounded-xl"></div> }>
+            {move || {
+                artists
+                    .get()
+                    .map(|artists| match artists {
+                        Ok(Some(artists)) => {
+                            let followed = followed_artists(followed);
+                            let following = followed_ids(&followed);
+                            let top: HashSet<String> = artists.iter().map(|artist| artist.id.to_string()).collect();
+
+                            // followed artists the user doesn't actually listen to much
+                            let unheard: Vec<FullArtist> = followed
+                                .into_iter()
+                                .filter(|artist| !top.contains(&artist.id.to_string()))
+                                .collect();
+
+                            view! {
+                                <div class="w-full max-w-md space-y-4">
+                                    <ol class="space-y-2">
+                                        {artists
+                                            .into_iter()
+                                            .enumerate()
+                                            .map(|(rank, artist)| {
+                                                let is_followed = following.contains(&artist.id.to_string());
+                                                artist_row(rank, artist, is_followed)
+                                            })
+                                            .collect_view()}
+                                    </ol>
+                                    {(!unheard.is_empty()).then(|| view! {
+                                        <div class="space-y-1">
+                                            <h3 class="font-bold">"Followed, but not in your top artists"</h3>
+                                            <ul class="flex flex-wrap gap-1">
+                                                {unheard
+                                                    .into_iter()
+                                                    .map(|artist| view! { <li class="badge badge-outline">{artist.name}</li> })
+                                                    .collect_view()}
+                                            </ul>
+                                        </div>
+                                    })}
+                                </div>
+                            }.into_view()
+                        }
+                        Ok(None) => ().into_view(),
+                        Err(err) => view! { <p>"An Error " {err.to_string()}</p> }.into_view(),
+                    })
+            }}
+
+        </Suspense>
+    }
+}
+
+/// The followed artists once loaded, or none if they couldn't be, so they never hide the rest of the dashboard.
+fn followed_artists(followed: Followed) -> Vec<FullArtist> {
+    followed.get().and_then(Result::ok).flatten().unwrap_or_default()
+}
+
+fn followed_ids(followed: &[FullArtist]) -> HashSet<String> {
+    followed.iter().map(|artist| artist.id.to_string()).collect()
+}
+
+#[component]
+pub fn TopTracks(#[prop(into)] range: Signal<TimeRange>, #[prop(into)] version: Signal<usize>) -> impl IntoView {
+    let tracks = create_resource(move || (range.get(), version.get()), |(range, _)| async move {
+        client::get_top_tracks(range).await });
+
+    let track_row = |(rank, track): (usize, client::TopTrack)| {
+        let artists = track
+            .artists
+            .iter()
+            .map(|artist| artist.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        view! {
+            <li class="flex items-center space-x-3">
+                <span class="w-6 text-right font-mono">{rank + 1}</span>
+                <div class="avatar">
+                    <div class="w-10 rounded">
+                        {track.album_image.map(|image| view! { <img src=image alt=track.album.clone()/> })}
+                    </div>
+                </div>
+                <div class="grow min-w-0">
+                    <p class="font-bold truncate">{track.name}</p>
+                    <p class="text-xs truncate">{artists}</p>
+                </div>
+                <span class="font-mono text-xs">{format_duration(track.duration_ms)}</span>
+            </li>
+        }
+    };
+
+    view! {
+        <Suspense fallback=move || view! { <div class="w-full max-w-md h-64 skeleton rounded-xl"></div> }>
+            {move || {
+                tracks
+                    .get()
+                    .map(|tracks| match tracks {
+                        Ok(Some(tracks)) => view! {
+                            <ol class="w-full max-w-md space-y-2">
+                                {tracks.into_iter().enumerate().map(track_row).collect_view()}
+                            </ol>
+                        }.into_view(),
+                        Ok(None) => ().into_view(),
+                        Err(err) => view! { <p>"An Error " {err.to_string()}</p> }.into_view(),
+                    })
+            }}
+
+        </Suspense>
+    }
+}
+
+/// Format a track length as `M:SS`.
+fn format_duration(duration_ms: i64) -> String {
+    let seconds = duration_ms / 1000;
+
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+#[component]
+pub fn GenreBreakdown(#[prop(into)] version: Signal<usize>) -> impl IntoView {
+    let breakdown = create_resource(move || version.get(), |_| async move {
+        constellation::genres::get_genre_breakdown().await });
+
+    let range_column = |range: constellation::genres::RangeGenres| {
+        let label = client::range_label(range.range);
+
+        view! {
+            <div class="w-full max-w-xs space-y-2">
+                <h3 class="font-bold text-center">{label}</h3>
+                <ul class="space-y-1">
+                    {range
+                        .genres
+                        .into_iter()
+                        .map(|genre| {
+                            view! {
+                                <li>
+                                    <div class="flex justify-between text-xs">
+                                        <span class="capitalize">{genre.genre}</span>
+                                        <span>{format!("{:.0}%", genre.share * 100.0)}</span>
+                                    </div>
+                                    <progress class="progress progress-accent" value=genre.share * 100.0 max="100"></progress>
+                                </li>
+                            }
+                        })
+                        .collect_view()}
+                </ul>
+            </div>
+        }
+    };
+
+    view! {
+        <Suspense fallback=move || view! { <div class="mx-auto w-full max-w-3xl h-48 skeleton rounded-xl"></div> }>
+            {move || {
+                breakdown
+                    .get()
+                    .map(|breakdown| match breakdown {
+                        Ok(Some(ranges)) => view! {
+                            <div class="flex flex-wrap justify-center gap-6">
+                                {ranges.into_iter().map(range_column).collect_view()}
+                            </div>
+                        }.into_view(),
+                        Ok(None) => ().into_view(),
+                        Err(err) => view! { <p>"An Error " {err.to_string()}</p> }.into_view(),
+                    })
+            }}
+
+        </Suspense>
+    }
+}
+
+#[component]
+pub fn User(#[prop(into)] version: Signal<usize>) -> impl IntoView {
+    let client = create_resource(move || version.get(), |_| async move {
         client::get_current_user().await });
 
     // "skeleton" <- THIS IS A LOAD BEARING COMMENT. I SHIT YOU NOT.
@@ -31,7 +449,7 @@ pub fn User() -> impl IntoView {
                                     .images
                                     .clone()
                                     .expect("no profile pictures")
-                                    .get(0)
+                                    .first()
                                     .expect("no profile picture")
                                     .url
                                     .clone()/>
@@ -41,8 +459,7 @@ pub fn User() -> impl IntoView {
             </div>
             <p class="text-xl font-bold" class:skeleton=user.is_none()>
                 {user
-                    .map(|user| user.display_name)
-                    .flatten()
+                    .and_then(|user| user.display_name)
                     .unwrap_or_default()}
             </p>
         </div>
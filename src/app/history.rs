@@ -0,0 +1,79 @@
+use leptos::*;
+use rspotify::model::TimeRange;
+
+use crate::history::{self, Movement, RankChange};
+
+/// How far back [`RankMovement`] can compare, in days.
+const PERIODS: [(u32, &str); 3] = [(7, "1 Week"), (30, "1 Month"), (365, "1 Year")];
+
+/// Which of the current top artists rose, fell, are new or dropped out.
+#[component]
+pub fn RankMovement(#[prop(into)] range: Signal<TimeRange>, #[prop(into)] version: Signal<usize>) -> impl IntoView {
+    let days = create_rw_signal(PERIODS[0].0);
+
+    let movement = create_resource(move || (range.get(), days.get(), version.get()), |(range, days, _)| async move {
+        history::get_rank_movement(range, days).await });
+
+    let change_row = |change: RankChange| {
+        let badge = match change.movement() {
+            Movement::Rising(by) => view! { <span class="badge badge-success badge-sm">{format!("▲ {by}")}</span> },
+            Movement::Falling(by) => view! { <span class="badge badge-error badge-sm">{format!("▼ {by}")}</span> },
+            Movement::Unchanged => view! { <span class="badge badge-ghost badge-sm">"–"</span> },
+            Movement::New => view! { <span class="badge badge-info badge-sm">"New"</span> },
+            Movement::Dropped => view! { <span class="badge badge-outline badge-sm">"Dropped"</span> },
+        };
+
+        view! {
+            <li class="flex items-center space-x-3">
+                <span class="w-6 text-right font-mono">
+                    {change.rank.or(change.previous_rank).map(|rank| rank.to_string())}
+                </span>
+                <span class="grow font-bold truncate" class:opacity-50=change.rank.is_none()>{change.artist.name}</span>
+                {badge}
+            </li>
+        }
+    };
+
+    view! {
+        <div class="mx-auto w-full max-w-md space-y-2">
+            <div class="flex items-center justify-between">
+                <h3 class="font-bold">"Rank Movement"</h3>
+                <div class="join">
+                    {PERIODS
+                        .map(|(period, label)| view! {
+                            <button
+                                class="join-item btn btn-xs"
+                                class:btn-active=move || days.get() == period
+                                on:click=move |_| days.set(period)
+                            >
+                                {label}
+                            </button>
+                        })
+                        .collect_view()}
+                </div>
+            </div>
+            <Suspense fallback=move || view! { <div class="h-48 skeleton rounded-xl"></div> }>
+                {move || {
+                    movement
+                        .get()
+                        .map(|movement| match movement {
+                            Ok(Some(movement)) => match movement.since {
+                                Some(since) => view! {
+                                    <p class="text-xs">{format!("Since {since}")}</p>
+                                    <ol class="space-y-1">
+                                        {movement.changes.into_iter().map(change_row).collect_view()}
+                                    </ol>
+                                }.into_view(),
+                                None => view! {
+                                    <p class="text-xs">"Your history starts today. Check back tomorrow to see how your taste changes."</p>
+                                }.into_view(),
+                            },
+                            Ok(None) => ().into_view(),
+                            Err(err) => view! { <p>"An Error " {err.to_string()}</p> }.into_view(),
+                        })
+                }}
+
+            </Suspense>
+        </div>
+    }
+}
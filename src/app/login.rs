@@ -5,10 +5,8 @@ use serde::{Serialize, Deserialize};
 
 #[cfg(feature = "ssr")]
 use {
-    crate::{LOGIN_STATE_KEY, client},
-    axum_extra::extract::cookie::{Cookie, SameSite},
-    http::{header, HeaderValue},
-    time::{Duration, OffsetDateTime},
+    crate::{client, origin::Origin},
+    axum_login::tower_sessions::Session,
 };
 
 #[component]
@@ -48,39 +46,26 @@ pub fn SpotifyButtons(
     }
 }
 
-/// Creates a unique spotify login URL and attaches the current unix time
-/// to the state-passthrough to the URL & client cookie to validate.
+/// Creates a unique spotify login URL with a random, single-use state
+/// (and PKCE code verifier, if used) that is remembered in the visitor's
+/// session to validate and finish the callback.
 #[server(Login)]
 pub async fn get_login_info() -> Result<LoginInfo, ServerFnError> {
     #[cfg(feature = "ssr")]
     {
-        let state = OffsetDateTime::now_utc().unix_timestamp().to_string();
-        let site_addr = use_context::<LeptosOptions>()
-            .expect("no leptos options provided")
-            .site_addr;
-
-        expect_context::<leptos_axum::ResponseOptions>().insert_header(
-            header::SET_COOKIE,
-            HeaderValue::from_str(
-                &Cookie::build(LOGIN_STATE_KEY, &state)
-                    .max_age(Duration::hours(1))
-                    .path("/")
-                    .same_site(SameSite::None)
-                    .domain(site_addr.ip().to_string())
-                    .finish()
-                    .to_string(),
-            )
-            .expect("create cookie HeaderValue"),
-        );
-
         let auth_session = use_context::<crate::auth::AuthSession>()
             .expect("no auth session provided");
 
-        let Some(url) = auth_session
+        let session = use_context::<Session>()
+            .expect("no session provided");
+
+        let origin = use_context::<Origin>()
+            .expect("no origin provided");
+
+        let url = auth_session
             .backend
-            .authorize_url(state) else {
-                return Err(ServerFnError::ServerError("Authorization URL Error".to_string()))
-            };
+            .start_login(&session, origin.redirect_uri())
+            .map_err(|err| ServerFnError::ServerError(format!("Error starting login: {err}")))?;
 
         return Ok(LoginInfo {
             user: client::get_current_user().await?.map(|user| user.display_name.unwrap_or("Unknown User".to_string())),
@@ -0,0 +1,152 @@
+use leptos::*;
+use leptos_router::*;
+use rspotify::model::TimeRange;
+
+use super::constellation::Constellation;
+use crate::{
+    client::range_label,
+    share::{self, ShareOptions, Snapshot},
+};
+
+/// The public page for a shared snapshot at `/s/:id`.
+#[component]
+pub fn SharedSnapshot() -> impl IntoView {
+    let params = use_params_map();
+    let id = move || params.with(|params| params.get("id").cloned().unwrap_or_default());
+
+    let snapshot = create_resource(id, |id| async move { share::get_snapshot(id).await });
+
+    view! {
+        <div class="grow p-4 space-y-6">
+            <Suspense fallback=move || view! { <div class="mx-auto w-full max-w-3xl aspect-square skeleton rounded-xl"></div> }>
+                {move || {
+                    snapshot
+                        .get()
+                        .map(|snapshot| match snapshot {
+                            Ok(Some(snapshot)) => view! { <SnapshotView snapshot /> }.into_view(),
+                            Ok(None) => view! {
+                                <div class="text-center space-y-2">
+                                    <h1 class="text-2xl font-bold">"This star map isn't shared anymore"</h1>
+                                    <A href="/" class="btn">"Make Your Own"</A>
+                                </div>
+                            }.into_view(),
+                            Err(err) => view! { <p>"An Error " {err.to_string()}</p> }.into_view(),
+                        })
+                }}
+
+            </Suspense>
+        </div>
+    }
+}
+
+#[component]
+fn SnapshotView(snapshot: Snapshot) -> impl IntoView {
+    let title = match snapshot.display_name {
+        Some(name) => format!("{name}'s Star Map"),
+        None => "A Star Map".to_string(),
+    };
+
+    view! {
+        <div class="text-center space-y-1">
+            <h1 class="text-2xl font-bold">{title}</h1>
+            {snapshot.range.map(|range| view! { <p>{range_label(range)}</p> })}
+        </div>
+        <Constellation graph=snapshot.constellation />
+        <div class="text-center">
+            <A href="/" class="btn btn-sm">"Make Your Own"</A>
+        </div>
+    }
+}
+
+/// Share the constellation for `range` and manage existing snapshots.
+#[component]
+pub fn ShareControls(#[prop(into)] range: Signal<TimeRange>) -> impl IntoView {
+    let share_action = create_server_action::<share::ShareConstellation>();
+    let revoke = create_server_action::<share::RevokeSnapshot>();
+
+    let show_name = create_rw_signal(false);
+    let show_range = create_rw_signal(true);
+
+    let snapshots = create_resource(
+        move || (share_action.version().get(), revoke.version().get()),
+        |_| async move { share::get_my_snapshots().await },
+    );
+
+    let share = move |_| {
+        share_action.dispatch(share::ShareConstellation {
+            range: range.get(),
+            options: ShareOptions {
+                show_name: show_name.get(),
+                show_range: show_range.get(),
+            },
+        })
+    };
+
+    let snapshot_row = move |snapshot: Snapshot| {
+        let href = format!("/s/{}", snapshot.id);
+        let id = snapshot.id.clone();
+
+        view! {
+            <li class="flex items-center justify-between space-x-2">
+                <A href=href.clone() class="link font-mono text-xs truncate">{href}</A>
+                <span class="text-xs">
+                    {snapshot.range.map(range_label).unwrap_or("Range hidden")}
+                </span>
+                <button
+                    class="btn btn-xs btn-error btn-outline"
+                    disabled=move || revoke.pending().get()
+                    on:click=move |_| revoke.dispatch(share::RevokeSnapshot { id: id.clone() })
+                >
+                    "Revoke"
+                </button>
+            </li>
+        }
+    };
+
+    view! {
+        <div class="mx-auto w-full max-w-md space-y-2">
+            <h3 class="font-bold">"Share Your Star Map"</h3>
+            <div class="flex flex-wrap items-center gap-4">
+                <label class="label cursor-pointer space-x-2">
+                    <input
+                        type="checkbox"
+                        class="checkbox checkbox-sm"
+                        prop:checked=move || show_name.get()
+                        on:change=move |ev| show_name.set(event_target_checked(&ev))
+                    />
+                    <span class="label-text">"Show my name"</span>
+                </label>
+                <label class="label cursor-pointer space-x-2">
+                    <input
+                        type="checkbox"
+                        class="checkbox checkbox-sm"
+                        prop:checked=move || show_range.get()
+                        on:change=move |ev| show_range.set(event_target_checked(&ev))
+                    />
+                    <span class="label-text">"Show time range"</span>
+                </label>
+                <button class="btn btn-sm" disabled=move || share_action.pending().get() on:click=share>
+                    "Share"
+                </button>
+            </div>
+            {move || match (share_action.value().get(), revoke.value().get()) {
+                (Some(Err(err)), _) | (_, Some(Err(err))) => Some(view! { <p class="text-xs text-error">{err.to_string()}</p> }),
+                _ => None,
+            }}
+            <Suspense>
+                {move || {
+                    snapshots
+                        .get()
+                        .and_then(Result::ok)
+                        .flatten()
+                        .map(|snapshots| view! {
+                            <ul class="space-y-1">
+                                {snapshots.into_iter().map(snapshot_row).collect_view()}
+                            </ul>
+                        })
+                }}
+
+            </Suspense>
+        </div>
+    }
+}
@@ -1,3 +1,8 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
 use async_trait::async_trait;
 use thiserror::Error;
 
@@ -5,12 +10,21 @@ use axum::{
     extract::Query,
     response::{IntoResponse, Redirect},
 };
-use axum_extra::extract::{cookie::Cookie, CookieJar};
-use axum_login::{AuthUser, AuthnBackend, UserId};
-use http::{header, HeaderValue, StatusCode};
-use rspotify::{clients::{OAuthClient, BaseClient}, AuthCodeSpotify, Token};
+use axum_login::{tower_sessions::{session, Session}, AuthUser, AuthnBackend, UserId};
+use http::StatusCode;
+use rspotify::{clients::{OAuthClient, BaseClient}, http::HttpError, ClientError, Token};
+
+use crate::{
+    origin::Origin,
+    store::{self, SharedStore, Store, DEFAULT_TREE},
+};
+
+mod spotify;
+mod state;
+pub mod tokens;
 
-use crate::{LOGIN_STATE_KEY, client};
+pub use spotify::SpotifyClient;
+pub use state::{PendingLogin, StateSigner, MAX_PENDING_STATES};
 
 /// An axum_login auth session wrapper type
 pub type AuthSession = axum_login::AuthSession<Backend>;
@@ -19,7 +33,7 @@ pub type AuthSession = axum_login::AuthSession<Backend>;
 #[derive(serde::Deserialize, Debug)]
 pub struct CallbackQuery {
     pub code: Option<String>,
-    pub state: i64,
+    pub state: String,
 }
 
 /// oauth2 redirect endpoint located at [`crate::CALLBACK_ENDPOINT`]
@@ -28,26 +42,20 @@ pub struct CallbackQuery {
 /// - `/dashboard` if authentication is successful 
 pub async fn authorize(
     mut auth_session: AuthSession,
+    session: Session,
+    origin: Origin,
     query: Query<CallbackQuery>,
-    jar: CookieJar,
 ) -> impl IntoResponse {
-    let state_cookie = jar.get(LOGIN_STATE_KEY);
-
-    if query.code.is_none()
-        || state_cookie.is_none()
-        || query.state.to_string() != state_cookie.unwrap().value()
-    {
-        return (
-            StatusCode::SEE_OTHER,
-            jar.remove(Cookie::named(LOGIN_STATE_KEY)),
-            [(header::LOCATION, HeaderValue::from_static("/"))],
-        )
-            .into_response();
-    }
+    // always consume the state, so it can't be replayed even if there's no code
+    let (Some(pending), Some(code)) = (auth_session.backend.consume_state(&session, &query.state), query.code.clone()) else {
+        return Redirect::to("/").into_response();
+    };
 
     let user = match auth_session
         .authenticate(Credentials {
-            code: query.code.clone().unwrap(),
+            code,
+            redirect_uri: origin.redirect_uri(),
+            verifier: pending.verifier,
         })
         .await
     {
@@ -59,18 +67,24 @@ pub async fn authorize(
         return StatusCode::INTERNAL_SERVER_ERROR.into_response();
     }
 
-    return (
-        StatusCode::SEE_OTHER,
-        jar.remove(Cookie::named(LOGIN_STATE_KEY)),
-        [(header::LOCATION, HeaderValue::from_static("/dashboard"))],
-    )
-        .into_response();
+    Redirect::to("/dashboard").into_response()
 }
 
-/// A backend type representing a user with their ID and [`rspotify::AuthCodeSpotify`].
+/// logout endpoint located at [`crate::LOGOUT_ENDPOINT`], POST only so
+/// nothing can log users out by linking or embedding it.
+/// Ends the current session and redirects to `/`
+pub async fn logout(mut auth_session: AuthSession) -> impl IntoResponse {
+    if auth_session.logout().is_err() {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    Redirect::to("/").into_response()
+}
+
+/// A backend type representing a user with their ID and [`SpotifyClient`].
 #[derive(Clone, Debug)]
 pub struct User {
-    pub client: AuthCodeSpotify,
+    pub client: SpotifyClient,
     pub user_id: String,
 }
 
@@ -89,6 +103,10 @@ impl AuthUser for User {
 #[derive(Debug, Clone)]
 pub struct Credentials {
     pub code: String,
+    /// Must match the redirect URI the login was started with.
+    pub redirect_uri: String,
+    /// The PKCE code verifier the login was started with, if the flow uses one.
+    pub verifier: Option<String>,
 }
 
 #[derive(Debug, Error)]
@@ -97,27 +115,128 @@ pub enum Error {
     Spotify(rspotify::ClientError),
 
     #[error(transparent)]
-    Sled(sled::Error)
+    Store(store::Error),
+
+    #[error(transparent)]
+    Session(session::Error),
+
+    #[error(transparent)]
+    Token(tokens::Error),
+
+    #[error("Spotify refused to refresh a token with status {status}: {body}")]
+    Refresh { status: u16, body: String },
 }
 
 #[derive(Debug, Clone)]
 pub struct Backend {
-    client: AuthCodeSpotify,
+    client: SpotifyClient,
+    state: StateSigner,
+    store: SharedStore,
+    /// Per-user locks held while refreshing a token, so concurrent requests
+    /// for the same user only refresh it once.
+    refresh_locks: Arc<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>>,
 }
 
 impl Backend {
-    pub fn new(client: AuthCodeSpotify) -> Self {
+    pub fn new(client: SpotifyClient, state: StateSigner, store: SharedStore) -> Self {
         Self {
             client,
+            state,
+            store,
+            refresh_locks: Arc::default(),
         }
     }
 
-    pub fn authorize_url(&self, state: String) -> Option<String> {
-        let mut client = self.client.clone();
+    /// Where users' tokens are stored.
+    pub fn store(&self) -> &dyn Store {
+        self.store.as_ref()
+    }
+
+    /// Start a login from `session`, returning the Spotify login page to send the visitor to.
+    ///
+    /// A fresh OAuth2 state is remembered in the session until the login is
+    /// finished, along with the PKCE code verifier if the flow uses one.
+    pub fn start_login(&self, session: &Session, redirect_uri: String) -> Result<String, Error> {
+        let state = self.state.create(session);
 
-        client.oauth.state = state;
+        let (url, verifier) = self
+            .client
+            .authorize_url(state.clone(), redirect_uri)
+            .map_err(Error::Spotify)?;
 
-        client.get_authorize_url(true).ok()
+        self.state
+            .remember(session, state, verifier)
+            .map_err(Error::Session)?;
+
+        Ok(url)
+    }
+
+    /// Check and use up an OAuth2 state returned to [`crate::CALLBACK_ENDPOINT`].
+    pub fn consume_state(&self, session: &Session, state: &str) -> Option<PendingLogin> {
+        self.state.consume(session, state)
+    }
+
+    /// A copy of the base client with its own token.
+    fn user_client(&self, token: Option<Token>) -> SpotifyClient {
+        self.client.with_token(token)
+    }
+
+    /// Exchange the stored refresh token of `user_id` for a new access token and store it.
+    ///
+    /// Returns `None` and forgets the stored token if Spotify no longer
+    /// accepts the refresh token, see [`revoked`].
+    async fn refresh_token(&self, user_id: &str) -> Result<Option<Token>, Error> {
+        let lock = self
+            .refresh_locks
+            .lock()
+            .expect("lock refresh locks")
+            .entry(user_id.to_string())
+            .or_default()
+            .clone();
+
+        let guard = lock.lock().await;
+        let result = self.refresh_token_locked(user_id).await;
+        drop(guard);
+
+        // forget the lock once nobody else holds it, the map and us aside
+        let mut locks = self.refresh_locks.lock().expect("lock refresh locks");
+        if Arc::strong_count(&lock) == 2 {
+            locks.remove(user_id);
+        }
+
+        result
+    }
+
+    /// [`Self::refresh_token`] while holding the lock of `user_id`.
+    async fn refresh_token_locked(&self, user_id: &str) -> Result<Option<Token>, Error> {
+        // another request may have refreshed the token while we were waiting
+        let Some(token) = load_token(self.store(), user_id)? else {
+            return Ok(None);
+        };
+
+        if !token.is_expired() {
+            return Ok(Some(token));
+        }
+
+        let client = self.user_client(Some(token));
+
+        if let Err(err) = client.refresh_token().await {
+            revoked(err).await?;
+
+            tracing::info!("Refresh token for {user_id} was revoked, logging out");
+
+            self.store.delete(DEFAULT_TREE, user_id).map_err(Error::Store)?;
+
+            return Ok(None);
+        }
+
+        let Some(token) = client.get_token().lock().await.expect("lock on token").clone() else {
+            return Ok(None);
+        };
+
+        tokens::put_token(self.store(), user_id, &token).map_err(Error::Token)?;
+
+        Ok(Some(token))
     }
 }
 
@@ -131,7 +250,14 @@ impl AuthnBackend for Backend {
         &self,
         creds: Self::Credentials,
     ) -> Result<Option<Self::User>, Self::Error> {
-        let client = self.client.clone();
+        // a PKCE login can't be finished without the verifier it was started with
+        if self.client.is_pkce() && creds.verifier.is_none() {
+            return Ok(None);
+        }
+
+        let mut client = self.user_client(None);
+        client.set_redirect_uri(creds.redirect_uri);
+        client.set_verifier(creds.verifier);
 
         client
             .request_token(&creds.code)
@@ -154,25 +280,26 @@ impl AuthnBackend for Backend {
             .clone()
             .expect("get client token");
 
-        client::put_to_db(&user.user_id, token)
-            .await
-            .map_err(Error::Sled)
+        tokens::put_token(self.store(), &user.user_id, &token)
+            .map_err(Error::Token)
             .map(|_| Some(user))
     }
 
     async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
-        let client = self.client.clone();
+        let Some(mut token) = load_token(self.store(), user_id)? else {
+            return Ok(None);
+        };
 
-        let Some(token) = client::get_from_db::<Token>(user_id)
-            .await
-            .map_err(Error::Sled)? else {
+        if token.is_expired() {
+            let Some(refreshed) = self.refresh_token(user_id).await? else {
                 return Ok(None);
             };
-        
-        *client.token.lock().await.expect("lock on token") = Some(token);
+
+            token = refreshed;
+        }
 
         let user = User {
-            client,
+            client: self.user_client(Some(token)),
             user_id: user_id.to_string(),
         };
 
@@ -180,3 +307,46 @@ impl AuthnBackend for Backend {
     }
 }
 
+/// The stored token of `user_id`.
+///
+/// A token that can't be decrypted, e.g. because its key was removed from
+/// the configuration, is treated like a missing one so the user has to log
+/// in again, but it's left in place in case the key comes back.
+fn load_token(store: &dyn Store, user_id: &str) -> Result<Option<Token>, Error> {
+    match tokens::get_token(store, user_id) {
+        Ok(token) => Ok(token),
+        Err(tokens::Error::Store(err)) => Err(Error::Store(err)),
+        Err(err) => {
+            tracing::error!("Ignoring stored token of {user_id}: {err}");
+            Ok(None)
+        }
+    }
+}
+
+/// `Ok` if a token refresh failed because Spotify no longer accepts the
+/// refresh token, e.g. because the user revoked access, or else the error to surface.
+///
+/// Rate limits and a misconfigured client are client errors too, but say
+/// nothing about the token, so only an `invalid_grant` counts.
+async fn revoked(err: ClientError) -> Result<(), Error> {
+    let ClientError::Http(err) = err else {
+        return Err(Error::Spotify(err));
+    };
+
+    let response = match *err {
+        HttpError::StatusCode(response) => response,
+        err => return Err(Error::Spotify(ClientError::Http(Box::new(err)))),
+    };
+
+    let status = response.status().as_u16();
+    let body = response.text().await.unwrap_or_default();
+
+    let invalid_grant = serde_json::from_str::<serde_json::Value>(&body)
+        .is_ok_and(|body| body["error"] == "invalid_grant");
+
+    match (status, invalid_grant) {
+        (400, true) => Ok(()),
+        _ => Err(Error::Refresh { status, body }),
+    }
+}
+
@@ -0,0 +1,140 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rspotify::{
+    clients::{BaseClient, OAuthClient},
+    http::HttpClient,
+    sync::Mutex,
+    AuthCodePkceSpotify, AuthCodeSpotify, ClientResult, Config, Credentials, OAuth, Token,
+};
+
+/// A Spotify client for whichever authorization flow is configured.
+///
+/// Both flows share every API call and only differ in how tokens are
+/// requested, so this is used wherever a user's client is needed.
+#[derive(Clone, Debug)]
+pub enum SpotifyClient {
+    /// The authorization code flow, which authenticates with the client secret.
+    AuthCode(AuthCodeSpotify),
+    /// The authorization code flow with PKCE, which needs no client secret.
+    Pkce(AuthCodePkceSpotify),
+}
+
+impl Default for SpotifyClient {
+    fn default() -> Self {
+        Self::AuthCode(AuthCodeSpotify::default())
+    }
+}
+
+impl SpotifyClient {
+    /// A copy of this client with its own `token`.
+    ///
+    /// Cloning an rspotify client shares its token between the clones,
+    /// so every user needs a fresh one.
+    pub fn with_token(&self, token: Option<Token>) -> Self {
+        let mut client = self.clone();
+        let token = Arc::new(Mutex::new(token));
+
+        match &mut client {
+            Self::AuthCode(client) => client.token = token,
+            Self::Pkce(client) => client.token = token,
+        }
+
+        client
+    }
+
+    /// Whether logins need a PKCE code verifier.
+    pub fn is_pkce(&self) -> bool {
+        matches!(self, Self::Pkce(_))
+    }
+
+    fn oauth_mut(&mut self) -> &mut OAuth {
+        match self {
+            Self::AuthCode(client) => &mut client.oauth,
+            Self::Pkce(client) => &mut client.oauth,
+        }
+    }
+
+    pub fn set_redirect_uri(&mut self, redirect_uri: String) {
+        self.oauth_mut().redirect_uri = redirect_uri;
+    }
+
+    /// Set the code verifier of the login being finished. Only used by PKCE.
+    pub fn set_verifier(&mut self, verifier: Option<String>) {
+        if let Self::Pkce(client) = self {
+            client.verifier = verifier;
+        }
+    }
+
+    /// The Spotify login page for `state`, along with the PKCE code verifier
+    /// to keep until the login is finished, if the flow uses one.
+    pub fn authorize_url(&self, state: String, redirect_uri: String) -> ClientResult<(String, Option<String>)> {
+        let mut client = self.clone();
+
+        client.oauth_mut().state = state;
+        client.set_redirect_uri(redirect_uri);
+
+        match &mut client {
+            Self::AuthCode(client) => Ok((client.get_authorize_url(true)?, None)),
+            Self::Pkce(client) => {
+                let url = client.get_authorize_url(None)?;
+                Ok((url, client.verifier.take()))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl BaseClient for SpotifyClient {
+    fn get_http(&self) -> &HttpClient {
+        match self {
+            Self::AuthCode(client) => client.get_http(),
+            Self::Pkce(client) => client.get_http(),
+        }
+    }
+
+    fn get_token(&self) -> Arc<Mutex<Option<Token>>> {
+        match self {
+            Self::AuthCode(client) => client.get_token(),
+            Self::Pkce(client) => client.get_token(),
+        }
+    }
+
+    fn get_creds(&self) -> &Credentials {
+        match self {
+            Self::AuthCode(client) => client.get_creds(),
+            Self::Pkce(client) => client.get_creds(),
+        }
+    }
+
+    fn get_config(&self) -> &Config {
+        match self {
+            Self::AuthCode(client) => client.get_config(),
+            Self::Pkce(client) => client.get_config(),
+        }
+    }
+
+    async fn refetch_token(&self) -> ClientResult<Option<Token>> {
+        match self {
+            Self::AuthCode(client) => client.refetch_token().await,
+            Self::Pkce(client) => client.refetch_token().await,
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthClient for SpotifyClient {
+    fn get_oauth(&self) -> &OAuth {
+        match self {
+            Self::AuthCode(client) => client.get_oauth(),
+            Self::Pkce(client) => client.get_oauth(),
+        }
+    }
+
+    async fn request_token(&self, code: &str) -> ClientResult<()> {
+        match self {
+            Self::AuthCode(client) => client.request_token(code).await,
+            Self::Pkce(client) => client.request_token(code).await,
+        }
+    }
+}
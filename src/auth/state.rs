@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+
+use axum_login::tower_sessions::{session, Session};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use time::{Duration, OffsetDateTime};
+
+use crate::LOGIN_STATE_KEY;
+
+/// How long a user has to finish logging in with Spotify.
+pub const STATE_TTL: Duration = Duration::minutes(10);
+
+/// How many logins a session can have started and not finished, e.g. in
+/// several tabs. Starting another one forgets the oldest.
+pub const MAX_PENDING_STATES: usize = 5;
+
+/// A state remembered by [`StateSigner::remember`] that hasn't been used yet.
+#[derive(Serialize, Deserialize)]
+struct PendingState {
+    state: String,
+    /// When the login was started, as a unix timestamp in nanoseconds.
+    #[serde(default)]
+    started_at: i128,
+    expires_at: i64,
+    /// Sessions from before PKCE support don't have one.
+    #[serde(default)]
+    verifier: Option<String>,
+}
+
+impl PendingState {
+    fn is_expired(&self) -> bool {
+        self.expires_at <= OffsetDateTime::now_utc().unix_timestamp()
+    }
+}
+
+/// Every pending state of a session, by the nonce it starts with.
+type PendingStates = BTreeMap<String, PendingState>;
+
+/// The nonce a state starts with, which is all of it if it isn't signed.
+fn nonce(state: &str) -> &str {
+    state.split_once('.').map_or(state, |(nonce, _)| nonce)
+}
+
+/// The pending states of `session`. Sessions from before there could be
+/// several only had one, which is dropped.
+fn pending_states(session: &Session) -> PendingStates {
+    session.get(LOGIN_STATE_KEY).ok().flatten().unwrap_or_default()
+}
+
+/// What a login was started with, returned once its state is used.
+#[derive(Clone, Debug)]
+pub struct PendingLogin {
+    /// The PKCE code verifier, if the flow uses one.
+    pub verifier: Option<String>,
+}
+
+/// Issues and checks the OAuth2 `state` passed through Spotify's login page.
+///
+/// States are random, remembered in the visitor's session until the
+/// callback uses them, and optionally signed with a server secret over the
+/// session ID, so they can't be guessed, replayed or moved to another session.
+#[derive(Clone, Default)]
+pub struct StateSigner {
+    secret: Option<Vec<u8>>,
+}
+
+impl std::fmt::Debug for StateSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateSigner")
+            .field("signed", &self.secret.is_some())
+            .finish()
+    }
+}
+
+impl StateSigner {
+    pub fn new(secret: Option<Vec<u8>>) -> Self {
+        Self { secret }
+    }
+
+    /// Create a new state for `session`, which is only accepted once it's remembered with [`Self::remember`].
+    pub fn create(&self, session: &Session) -> String {
+        let mut nonce = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let nonce = URL_SAFE_NO_PAD.encode(nonce);
+
+        match self.mac(&nonce, session) {
+            Some(mac) => format!("{nonce}.{}", URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())),
+            None => nonce,
+        }
+    }
+
+    /// Remember `state` in `session` until the callback, along with the PKCE
+    /// code verifier of the login, next to the states of other logins
+    /// started from the session, up to [`MAX_PENDING_STATES`].
+    pub fn remember(&self, session: &Session, state: String, verifier: Option<String>) -> Result<(), session::Error> {
+        let mut pending = pending_states(session);
+        pending.retain(|_, pending| !pending.is_expired());
+
+        while pending.len() >= MAX_PENDING_STATES {
+            let oldest = pending
+                .iter()
+                .min_by_key(|(_, pending)| pending.started_at)
+                .map(|(nonce, _)| nonce.clone())
+                .expect("there are pending states");
+
+            pending.remove(&oldest);
+        }
+
+        let now = OffsetDateTime::now_utc();
+
+        pending.insert(nonce(&state).to_string(), PendingState {
+            state,
+            started_at: now.unix_timestamp_nanos(),
+            expires_at: (now + STATE_TTL).unix_timestamp(),
+            verifier,
+        });
+
+        session.insert(LOGIN_STATE_KEY, pending)
+    }
+
+    /// Check `state` against the ones remembered in `session`, returning what its login was started with.
+    ///
+    /// The state is forgotten either way, so every state is only accepted
+    /// once, while the other logins of the session can still be finished.
+    pub fn consume(&self, session: &Session, state: &str) -> Option<PendingLogin> {
+        let mut states = pending_states(session);
+        let pending = states.remove(nonce(state))?;
+
+        states.retain(|_, pending| !pending.is_expired());
+
+        let saved = match states.is_empty() {
+            true => session.remove::<PendingStates>(LOGIN_STATE_KEY).map(|_| ()),
+            false => session.insert(LOGIN_STATE_KEY, states),
+        };
+
+        if saved.is_err() || pending.state != state || pending.is_expired() {
+            return None;
+        }
+
+        let valid = match self.secret {
+            Some(_) => self.verify(state, session),
+            None => true,
+        };
+
+        valid.then_some(PendingLogin {
+            verifier: pending.verifier,
+        })
+    }
+
+    fn verify(&self, state: &str, session: &Session) -> bool {
+        let Some((nonce, signature)) = state.split_once('.') else {
+            return false;
+        };
+
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+
+        self.mac(nonce, session)
+            .is_some_and(|mac| mac.verify_slice(&signature).is_ok())
+    }
+
+    fn mac(&self, nonce: &str, session: &Session) -> Option<Hmac<Sha256>> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_ref()?)
+            .expect("HMAC accepts keys of any length");
+
+        mac.update(nonce.as_bytes());
+        mac.update(b".");
+        mac.update(session.id().to_string().as_bytes());
+
+        Some(mac)
+    }
+}
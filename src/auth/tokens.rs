@@ -0,0 +1,147 @@
+//! Spotify tokens stored in the database, encrypted with the configured [`TokenKey`]s.
+//!
+//! A sealed entry is [`SEALED_MAGIC`], the length of the key ID, the key ID,
+//! a random nonce and the ChaCha20-Poly1305 encrypted bincode of the
+//! [`Token`], authenticated together with the user ID it's stored under so
+//! it can't be moved to another account.
+
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use rspotify::Token;
+use thiserror::Error;
+
+use crate::{
+    config::TokenKey,
+    store::{self, Store, DEFAULT_TREE},
+};
+
+lazy_static::lazy_static! {
+    static ref CIPHER: TokenCipher = TokenCipher::new(crate::config::get().token_keys.clone());
+}
+
+/// Start of every sealed entry. Unsealed entries are plain bincode, which
+/// starts with the length of the access token and so never looks like this.
+const SEALED_MAGIC: &[u8; 4] = b"STK1";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("token was encrypted with key {0:?}, which isn't configured")]
+    UnknownKey(String),
+
+    #[error("token could not be decrypted with key {0:?}, the key may be wrong")]
+    Decrypt(String),
+
+    #[error("token entry is malformed")]
+    Malformed,
+
+    #[error(transparent)]
+    Store(#[from] store::Error),
+}
+
+/// Encrypts and decrypts stored tokens with a list of keys, the first of which is current.
+pub struct TokenCipher {
+    keys: Vec<(String, LessSafeKey)>,
+}
+
+impl TokenCipher {
+    pub fn new(keys: Vec<TokenKey>) -> Self {
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|key| {
+                    let cipher = UnboundKey::new(&CHACHA20_POLY1305, &key.key).expect("32 byte ChaCha20-Poly1305 key");
+                    (key.id, LessSafeKey::new(cipher))
+                })
+                .collect(),
+        }
+    }
+
+    /// Encode `token` for storing under `user_id`, encrypted with the current key if there is one.
+    pub fn seal(&self, user_id: &str, token: &Token) -> Vec<u8> {
+        let plain = bincode::serialize(token).expect("parse to bincode");
+
+        let Some((id, key)) = self.keys.first() else {
+            return plain;
+        };
+
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut sealed = plain;
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(user_id.as_bytes()), &mut sealed)
+            .expect("token fits in a ChaCha20-Poly1305 message");
+
+        let mut entry = Vec::with_capacity(SEALED_MAGIC.len() + 1 + id.len() + NONCE_LEN + sealed.len());
+        entry.extend(SEALED_MAGIC);
+        entry.push(id.len() as u8);
+        entry.extend(id.as_bytes());
+        entry.extend(nonce);
+        entry.extend(sealed);
+
+        entry
+    }
+
+    /// Decode an entry stored under `user_id`, also returning whether it
+    /// should be sealed again because it isn't encrypted with the current key.
+    pub fn open(&self, user_id: &str, entry: &[u8]) -> Result<(Token, bool), Error> {
+        let current = self.keys.first().map(|(id, _)| id.as_str());
+
+        let Some(rest) = entry.strip_prefix(SEALED_MAGIC) else {
+            let token = bincode::deserialize(entry).map_err(|_| Error::Malformed)?;
+            return Ok((token, current.is_some()));
+        };
+
+        let (&id_len, rest) = rest.split_first().ok_or(Error::Malformed)?;
+        if rest.len() < id_len as usize + NONCE_LEN {
+            return Err(Error::Malformed);
+        }
+
+        let (id, rest) = rest.split_at(id_len as usize);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let id = String::from_utf8_lossy(id).into_owned();
+
+        let Some((_, key)) = self.keys.iter().find(|(key_id, _)| *key_id == id) else {
+            return Err(Error::UnknownKey(id));
+        };
+
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| Error::Malformed)?;
+        let mut sealed = sealed.to_vec();
+
+        let plain = key
+            .open_in_place(nonce, Aad::from(user_id.as_bytes()), &mut sealed)
+            .map_err(|_| Error::Decrypt(id.clone()))?;
+
+        let token = bincode::deserialize(plain).map_err(|_| Error::Malformed)?;
+
+        Ok((token, current != Some(id.as_str())))
+    }
+}
+
+/// Whether `entry` reads as a stored token, sealed or not, without decrypting it.
+pub fn is_token_entry(entry: &[u8]) -> bool {
+    entry.starts_with(SEALED_MAGIC) || bincode::deserialize::<Token>(entry).is_ok()
+}
+
+/// The stored token of `user_id`, re-encrypted with the current key if it isn't already.
+pub fn get_token(store: &dyn Store, user_id: &str) -> Result<Option<Token>, Error> {
+    let Some(entry) = store.get(DEFAULT_TREE, user_id)? else {
+        return Ok(None);
+    };
+
+    let (token, reseal) = CIPHER.open(user_id, &entry)?;
+
+    if reseal {
+        // only replace the entry if nobody stored a new token in the meantime
+        let resealed = CIPHER.seal(user_id, &token);
+        store.compare_and_swap(DEFAULT_TREE, user_id, Some(&entry), Some(resealed))?;
+    }
+
+    Ok(Some(token))
+}
+
+/// Store `token` for `user_id`, encrypted with the current key.
+pub fn put_token(store: &dyn Store, user_id: &str, token: &Token) -> Result<(), Error> {
+    store.put(DEFAULT_TREE, user_id, CIPHER.seal(user_id, token), None)?;
+
+    Ok(())
+}
@@ -1,53 +1,329 @@
 use leptos::*;
-use rspotify::model::{PrivateUser, TimeRange, FullArtist};
+use rspotify::model::{PrivateUser, TimeRange, FullArtist, FullTrack};
+use serde::{Deserialize, Serialize};
 
 cfg_if::cfg_if! {   
     if #[cfg(feature = "ssr")] {
-        use crate::auth::AuthSession;
-        use serde::{de::DeserializeOwned, Serialize};
+        use crate::{
+            auth::{AuthSession, User},
+            store::{self, schema::Tagged, user_key, user_prefix, SharedStore, Store, Stored, DEFAULT_TREE},
+        };
+        use time::{Duration, OffsetDateTime};
+
+        /// Name of the tree cached Spotify responses are stored in.
+        pub const CACHE_TREE: &str = "cache";
+
+        /// How long responses for a [`TimeRange`] stay cached.
+        /// Longer ranges change more slowly, so they're refreshed less often.
+        pub fn range_ttl(range: TimeRange) -> Duration {
+            let cache = &crate::config::get().cache;
+
+            match range {
+                TimeRange::ShortTerm => cache.short_term_ttl,
+                TimeRange::MediumTerm => cache.medium_term_ttl,
+                TimeRange::LongTerm => cache.long_term_ttl,
+            }
+        }
+
+        /// The start of every [`CacheEntry`], used to read when it was fetched without knowing the value's type.
+        #[derive(Serialize, Deserialize)]
+        struct CacheHeader {
+            fetched_at: i64,
+        }
+
+        /// A cached value along with when it was fetched and when it expires, as unix timestamps.
+        #[derive(Serialize, Deserialize)]
+        pub struct CacheEntry<V> {
+            pub fetched_at: i64,
+            pub expires_at: i64,
+            pub value: V,
+        }
+
+        /// Cache entries are tagged like the value they hold.
+        impl<V: Stored> Stored for CacheEntry<V> {
+            const TAG: &'static str = V::TAG;
+            const VERSION: u16 = V::VERSION;
+        }
+
+        impl Stored for PrivateUser {
+            const TAG: &'static str = "private_user";
+        }
+
+        impl Stored for Vec<FullArtist> {
+            const TAG: &'static str = "full_artists";
+        }
+
+        impl Stored for Vec<TopTrack> {
+            const TAG: &'static str = "top_tracks";
+        }
+
+        /// Get a value from [`CACHE_TREE`], treating unreadable entries as missing.
+        /// Expired entries are already gone, since they're stored with a TTL.
+        pub async fn get_from_cache<V: Stored>(store: &dyn Store, key: &str) -> Result<Option<V>, store::Error> {
+            Ok(store::get_value::<CacheEntry<V>>(store, CACHE_TREE, key)?.map(|entry| entry.value))
+        }
+
+        /// Insert a value into [`CACHE_TREE`] that expires after `ttl`.
+        pub async fn put_to_cache<V: Stored>(store: &dyn Store, key: &str, value: V, ttl: Duration) -> Result<Option<V>, store::Error> {
+            let now = OffsetDateTime::now_utc();
+            let entry = CacheEntry {
+                fetched_at: now.unix_timestamp(),
+                expires_at: (now + ttl).unix_timestamp(),
+                value,
+            };
+
+            store::put_value(store, CACHE_TREE, key, &entry, Some(ttl))?;
+
+            Ok(Some(entry.value))
+        }
+
+        /// When the entry under `key` in [`CACHE_TREE`] was fetched, as a unix timestamp.
+        pub async fn cache_fetched_at(store: &dyn Store, key: &str) -> Result<Option<i64>, store::Error> {
+            // bincode ignores the trailing fields, so only the header is decoded
+            Ok(store
+                .get(CACHE_TREE, key)?
+                .and_then(|out| {
+                    let tagged = Tagged::parse(&out).ok()?;
+                    bincode::deserialize::<CacheHeader>(tagged.payload).ok()
+                })
+                .map(|header| header.fetched_at))
+        }
+
+        /// Remove every entry in [`CACHE_TREE`] whose key starts with `prefix`.
+        pub async fn invalidate_cache(store: &dyn Store, prefix: &str) -> Result<(), store::Error> {
+            for (key, _) in store.scan_prefix(CACHE_TREE, prefix)? {
+                store.delete(CACHE_TREE, &key)?;
+            }
+
+            Ok(())
+        }
+
+        /// Remove everything stored about `user_id`: their token, cooldowns, cached responses, shared snapshots and history.
+        pub async fn delete_user_data(store: &dyn Store, user_id: &str) -> Result<(), store::Error> {
+            store.delete(DEFAULT_TREE, user_id)?;
+
+            crate::share::delete_user_snapshots(store, user_id)?;
+            crate::history::delete_user_history(store, user_id)?;
+
+            for (key, _) in store.scan_prefix(DEFAULT_TREE, &user_prefix(user_id))? {
+                store.delete(DEFAULT_TREE, &key)?;
+            }
+
+            invalidate_cache(store, &user_prefix(user_id)).await
+        }
+
+        /// Prune expired entries, like cached responses, from `store` every `period`, forever.
+        pub async fn continuously_prune_cache(store: SharedStore, period: std::time::Duration) {
+            let mut interval = tokio::time::interval(period);
+
+            loop {
+                interval.tick().await;
+
+                match store.prune_expired() {
+                    Ok(0) => (),
+                    Ok(pruned) => tracing::debug!("Pruned {pruned} expired cache entries"),
+                    Err(err) => tracing::error!("Error pruning cache: {err}"),
+                }
+            }
+        }
+
+        /// How a fetch in [`FETCHES`] went, once it's done.
+        type FetchOutcome = Option<Result<(), ServerFnError>>;
 
         lazy_static::lazy_static! {
-            pub static ref DATABASE: sled::Db = sled::open(std::env::var("STARIFY_CACHE").unwrap_or("starify_cache".to_string())).expect("create database");
+            /// Fetches of cache keys from Spotify in progress, see [`get_or_fetch`].
+            static ref FETCHES: std::sync::Mutex<std::collections::HashMap<String, tokio::sync::watch::Receiver<FetchOutcome>>> =
+                Default::default();
+        }
+
+        /// Removes a fetch from [`FETCHES`] once it's done, or cancelled.
+        struct FetchGuard<'a> {
+            key: &'a str,
+        }
+
+        impl Drop for FetchGuard<'_> {
+            fn drop(&mut self) {
+                FETCHES.lock().expect("lock fetches").remove(self.key);
+            }
+        }
+
+        /// Whether to fetch a key or wait for someone else's fetch of it.
+        enum FetchTurn {
+            Fetch(tokio::sync::watch::Sender<FetchOutcome>),
+            Wait(tokio::sync::watch::Receiver<FetchOutcome>),
+        }
+
+        /// The value cached under `key`, or else the result of `fetch`, cached for `ttl`.
+        ///
+        /// Concurrent misses for the same key share one fetch: the first one
+        /// fetches while the others wait, then read its result from the cache
+        /// or fail with its error. Misses after it has finished fetch again.
+        pub async fn get_or_fetch<V, F, Fut>(store: &dyn Store, key: &str, ttl: Duration, fetch: F) -> Result<V, ServerFnError>
+        where
+            V: Stored,
+            F: FnOnce() -> Fut,
+            Fut: std::future::Future<Output = Result<V, ServerFnError>>,
+        {
+            // leptos keeps the current request's runtime in a thread local, which
+            // other requests on this thread replace while this waits or fetches,
+            // so server functions returning straight after need it back
+            crate::server::with_request_runtime(async move {
+                loop {
+                    if let Ok(Some(value)) = get_from_cache(store, key).await {
+                        return Ok(value);
+                    }
+
+                    let turn = {
+                        let mut fetches = FETCHES.lock().expect("lock fetches");
+
+                        match fetches.get(key) {
+                            Some(running) => FetchTurn::Wait(running.clone()),
+                            None => {
+                                let (sender, receiver) = tokio::sync::watch::channel(None);
+                                fetches.insert(key.to_string(), receiver);
+                                FetchTurn::Fetch(sender)
+                            }
+                        }
+                    };
+
+                    match turn {
+                        FetchTurn::Wait(mut running) => {
+                            let outcome = running.wait_for(Option::is_some).await.map(|outcome| outcome.clone());
+
+                            // on success the value is cached now, and if the fetch
+                            // was cancelled, the next one in line fetches instead
+                            if let Ok(Some(Err(err))) = outcome {
+                                return Err(err);
+                            }
+                        }
+                        FetchTurn::Fetch(sender) => {
+                            let guard = FetchGuard { key };
+
+                            // a fetch may have finished since the cache was read
+                            let result = match get_from_cache(store, key).await {
+                                Ok(Some(value)) => Ok(value),
+                                _ => match fetch().await {
+                                    Ok(value) => cache_value(store, key, value, ttl).await,
+                                    Err(err) => Err(err),
+                                },
+                            };
+
+                            // misses from now on fetch again rather than get this outcome
+                            drop(guard);
+                            sender.send_replace(Some(result.as_ref().map(|_| ()).map_err(Clone::clone)));
+
+                            return result;
+                        }
+                    }
+                }
+            })
+            .await
         }
 
-        pub async fn get_from_db<V: DeserializeOwned>(key: &str) -> Result<Option<V>, sled::Error> {
-            DATABASE.get(key).map(|out| out.map(|out| bincode::deserialize(&out).expect("parse as bincode")))
+        pub async fn get_from_db<V: Stored>(store: &dyn Store, key: &str) -> Result<Option<V>, store::Error> {
+            store::get_value(store, DEFAULT_TREE, key)
         }
 
-        pub async fn put_to_db<V: Serialize>(key: &str, value: V) -> Result<Option<V>, sled::Error> {
-            DATABASE.insert(key, bincode::serialize(&value).expect("parse to bincode"))?;
+        pub async fn put_to_db<V: Stored>(store: &dyn Store, key: &str, value: V) -> Result<Option<V>, store::Error> {
+            store::put_value(store, DEFAULT_TREE, key, &value, None)?;
 
             Ok(Some(value))
         }
     }
 }
 
+/// How recently a user's data was pulled from Spotify, as unix timestamps.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RefreshStatus {
+    /// When the user's profile was last fetched, if it's cached.
+    pub refreshed_at: Option<i64>,
+    /// When [`refresh_data`] may be called again, if it's on cooldown.
+    pub available_at: Option<i64>,
+}
+
+/// The parts of a [`FullTrack`] starify shows and links artists with.
+///
+/// Tracks are cached as this rather than [`FullTrack`], which skips empty
+/// fields when serializing and so can't be read back from bincode.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TopTrack {
+    /// Local files have no ID.
+    pub id: Option<String>,
+    pub name: String,
+    pub artists: Vec<TrackArtist>,
+    pub album: String,
+    pub album_image: Option<String>,
+    pub duration_ms: i64,
+}
+
+/// An artist credited on a [`TopTrack`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TrackArtist {
+    pub id: Option<String>,
+    pub name: String,
+}
+
+impl From<FullTrack> for TopTrack {
+    fn from(track: FullTrack) -> Self {
+        Self {
+            id: track.id.map(|id| id.to_string()),
+            name: track.name,
+            artists: track
+                .artists
+                .into_iter()
+                .map(|artist| TrackArtist {
+                    id: artist.id.map(|id| id.to_string()),
+                    name: artist.name,
+                })
+                .collect(),
+            album: track.album.name,
+            album_image: track.album.images.first().map(|image| image.url.clone()),
+            duration_ms: track.duration.num_milliseconds(),
+        }
+    }
+}
+
+/// Parse the `range` query parameter used by the dashboard (`short`, `medium` or `long`).
+pub fn range_from_query(value: &str) -> Option<TimeRange> {
+    match value {
+        "short" => Some(TimeRange::ShortTerm),
+        "medium" => Some(TimeRange::MediumTerm),
+        "long" => Some(TimeRange::LongTerm),
+        _ => None,
+    }
+}
+
+/// How a [`TimeRange`] is labelled on the dashboard and in exports.
+pub fn range_label(range: TimeRange) -> &'static str {
+    match range {
+        TimeRange::ShortTerm => "Last 4 Weeks",
+        TimeRange::MediumTerm => "Last 6 Months",
+        TimeRange::LongTerm => "All Time",
+    }
+}
+
+/// The inverse of [`range_from_query`].
+pub fn range_to_query(range: TimeRange) -> &'static str {
+    match range {
+        TimeRange::ShortTerm => "short",
+        TimeRange::MediumTerm => "medium",
+        TimeRange::LongTerm => "long",
+    }
+}
+
 #[server]
 pub async fn get_current_user() -> Result<Option<PrivateUser>, ServerFnError> {
     #[cfg(feature = "ssr")]
     {
-        use rspotify::clients::OAuthClient;
-
         let Some(user) = use_context::<AuthSession>()
             .expect("no auth session provided")
             .user else {
                 return Ok(None);
             };
 
-        let userinfo_key = format!("{}_userinfo", user.user_id);
-
-        // get user from cache from user id
-        match get_from_db::<PrivateUser>(&userinfo_key).await {
-            // if successful & exists, deserialize the result
-            Ok(Some(user)) => Ok(Some(user)),
-            // if unsuccessful or doesn't exist, fetch from API
-            _ => match user.client.current_user().await {
-                // if successful, insert that into the database
-                Ok(me) => put_to_db::<PrivateUser>(&userinfo_key, me).await.map_err(|err| ServerFnError::ServerError(format!("Error inserting into cache: {err}"))),
-                // if API failed, err out to client
-                Err(err) => Err(ServerFnError::ServerError(format!("Error fetching from spotify: {err}")))
-            }
-        }
+        let store = use_context::<SharedStore>().expect("no store provided");
+
+        current_user(&*store, &user).await.map(Some)
     }
 }
 
@@ -55,27 +331,293 @@ pub async fn get_current_user() -> Result<Option<PrivateUser>, ServerFnError> {
 pub async fn get_top_artists(range: TimeRange) -> Result<Option<Vec<FullArtist>>, ServerFnError> {
     #[cfg(feature = "ssr")]
     {
-        use rspotify::clients::OAuthClient;
-        use futures_util::TryStreamExt;
+        let Some(user) = use_context::<AuthSession>()
+            .expect("no auth session provided")
+            .user else {
+                return Ok(None);
+            };
+
+        let store = use_context::<SharedStore>().expect("no store provided");
+
+        top_artists(&*store, &user, range).await.map(Some)
+    }
+}
+
+#[server]
+pub async fn get_top_tracks(range: TimeRange) -> Result<Option<Vec<TopTrack>>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        let Some(user) = use_context::<AuthSession>()
+            .expect("no auth session provided")
+            .user else {
+                return Ok(None);
+            };
+
+        let store = use_context::<SharedStore>().expect("no store provided");
+
+        top_tracks(&*store, &user, range).await.map(Some)
+    }
+}
 
+/// Every artist the current user follows, whether or not they listen to them.
+#[server]
+pub async fn get_followed_artists() -> Result<Option<Vec<FullArtist>>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
         let Some(user) = use_context::<AuthSession>()
             .expect("no auth session provided")
             .user else {
                 return Ok(None);
             };
 
-        let topartists_key = format!("{}_topartists_{range:?}", user.user_id);
+        let store = use_context::<SharedStore>().expect("no store provided");
 
-        // get user from cache from user id
-        match get_from_db::<Vec<FullArtist>>(&topartists_key).await {
-            // if successful & exists, deserialize the result
-            Ok(Some(top)) => Ok(Some(top)),
-            _ => {
-                match user.client.current_user_top_artists(Some(range)).try_collect().await {
-                    Ok(top) => put_to_db::<Vec<FullArtist>>(&topartists_key, top).await.map_err(|err| ServerFnError::ServerError(format!("Error inserting into cache: {err}"))),
-                    Err(err) => Err(ServerFnError::ServerError(err.to_string())),
-                }
-            }
+        followed_artists(&*store, &user).await.map(Some)
+    }
+}
+
+// The functions below back the server functions above. They take the user
+// explicitly, so handlers outside of leptos (like exports) can use them too.
+
+/// The profile of `user`, from the cache if possible.
+#[cfg(feature = "ssr")]
+pub async fn current_user(store: &dyn Store, user: &User) -> Result<PrivateUser, ServerFnError> {
+    use rspotify::clients::OAuthClient;
+
+    let userinfo_key = user_key(&user.user_id, "userinfo");
+
+    get_or_fetch(store, &userinfo_key, crate::config::get().cache.userinfo_ttl, || async {
+        user.client
+            .current_user()
+            .await
+            .map_err(|err| ServerFnError::ServerError(format!("Error fetching from spotify: {err}")))
+    })
+    .await
+}
+
+/// The top artists of `user` for `range`, from the cache if possible.
+///
+/// Freshly fetched artists are recorded as today's history snapshot. Cached
+/// ones aren't, they may be days old.
+#[cfg(feature = "ssr")]
+pub async fn top_artists(store: &dyn Store, user: &User, range: TimeRange) -> Result<Vec<FullArtist>, ServerFnError> {
+    let topartists_key = user_key(&user.user_id, &format!("topartists_{range:?}"));
+
+    get_or_fetch(store, &topartists_key, range_ttl(range), || async {
+        let top = fetch_top_artists(user, range).await?;
+
+        // history is best effort, it shouldn't keep the dashboard from loading
+        if let Err(err) = crate::history::record(store, &user.user_id, range, &top) {
+            tracing::error!("Error recording history: {err}");
         }
+
+        Ok(top)
+    })
+    .await
+}
+
+/// The top artists of `user` for `range`, straight from Spotify.
+#[cfg(feature = "ssr")]
+pub async fn fetch_top_artists(user: &User, range: TimeRange) -> Result<Vec<FullArtist>, ServerFnError> {
+    use rspotify::clients::OAuthClient;
+    use futures_util::TryStreamExt;
+
+    user.client
+        .current_user_top_artists(Some(range))
+        .try_collect()
+        .await
+        .map_err(|err| ServerFnError::ServerError(err.to_string()))
+}
+
+/// The top tracks of `user` for `range`, from the cache if possible.
+#[cfg(feature = "ssr")]
+pub async fn top_tracks(store: &dyn Store, user: &User, range: TimeRange) -> Result<Vec<TopTrack>, ServerFnError> {
+    use rspotify::clients::OAuthClient;
+    use futures_util::TryStreamExt;
+
+    let toptracks_key = user_key(&user.user_id, &format!("toptracks_{range:?}"));
+
+    get_or_fetch(store, &toptracks_key, range_ttl(range), || async {
+        user.client
+            .current_user_top_tracks(Some(range))
+            .map_ok(TopTrack::from)
+            .try_collect()
+            .await
+            .map_err(|err| ServerFnError::ServerError(err.to_string()))
+    })
+    .await
+}
+
+/// The artists `user` follows, from the cache if possible.
+#[cfg(feature = "ssr")]
+pub async fn followed_artists(store: &dyn Store, user: &User) -> Result<Vec<FullArtist>, ServerFnError> {
+    let followed_key = user_key(&user.user_id, "followed");
+
+    // follows change about as rarely as the profile, so they share its ttl
+    get_or_fetch(store, &followed_key, crate::config::get().cache.userinfo_ttl, || async {
+        fetch_followed_artists(&user.client)
+            .await
+            .map_err(|err| ServerFnError::ServerError(err.to_string()))
+    })
+    .await
+}
+
+/// [`put_to_cache`] for [`get_or_fetch`], returning the value itself.
+#[cfg(feature = "ssr")]
+async fn cache_value<V: Stored>(store: &dyn Store, key: &str, value: V, ttl: Duration) -> Result<V, ServerFnError> {
+    put_to_cache(store, key, value, ttl)
+        .await
+        .map(|value| value.expect("put_to_cache returns the value"))
+        .map_err(|err| ServerFnError::ServerError(format!("Error inserting into cache: {err}")))
+}
+
+/// Page through the cursor-based followed artists endpoint, which rspotify doesn't paginate for us.
+#[cfg(feature = "ssr")]
+async fn fetch_followed_artists(client: &crate::auth::SpotifyClient) -> rspotify::ClientResult<Vec<FullArtist>> {
+    use rspotify::clients::OAuthClient;
+
+    let mut followed = Vec::new();
+    let mut after = None;
+
+    loop {
+        let page = client.current_user_followed_artists(after.as_deref(), Some(50)).await?;
+
+        followed.extend(page.items);
+
+        match page.cursors.and_then(|cursors| cursors.after) {
+            Some(cursor) if page.next.is_some() => after = Some(cursor),
+            _ => return Ok(followed),
+        }
+    }
+}
+
+#[server]
+pub async fn get_refresh_status() -> Result<Option<RefreshStatus>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        let Some(user) = use_context::<AuthSession>()
+            .expect("no auth session provided")
+            .user else {
+                return Ok(None);
+            };
+
+        let store = use_context::<SharedStore>().expect("no store provided");
+
+        refresh_status(&*store, &user.user_id)
+            .await
+            .map(Some)
+            .map_err(|err| ServerFnError::ServerError(format!("Error reading from cache: {err}")))
     }
-}
\ No newline at end of file
+}
+
+/// Drop everything cached for the current user and fetch it again from Spotify.
+/// Only allowed once per refresh cooldown for each user.
+#[server]
+pub async fn refresh_data() -> Result<Option<RefreshStatus>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        crate::server::with_request_runtime(async move {
+            let Some(user) = use_context::<AuthSession>()
+                .expect("no auth session provided")
+                .user else {
+                    return Ok(None);
+                };
+
+            let store = use_context::<SharedStore>().expect("no store provided");
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+
+            if let Some(available_at) = claim_refresh(&*store, &user.user_id, now)
+                .map_err(|err| ServerFnError::ServerError(format!("Error inserting into cache: {err}")))?
+            {
+                return Err(ServerFnError::ServerError(format!(
+                    "Data was refreshed too recently, try again in {} minutes",
+                    (available_at - now + 59) / 60
+                )));
+            }
+
+            invalidate_cache(&*store, &user_prefix(&user.user_id))
+                .await
+                .map_err(|err| ServerFnError::ServerError(format!("Error clearing cache: {err}")))?;
+
+            get_current_user().await?;
+            get_followed_artists().await?;
+
+            for range in [TimeRange::ShortTerm, TimeRange::MediumTerm, TimeRange::LongTerm] {
+                get_top_artists(range).await?;
+                get_top_tracks(range).await?;
+            }
+
+            refresh_status(&*store, &user.user_id)
+                .await
+                .map(Some)
+                .map_err(|err| ServerFnError::ServerError(format!("Error reading from cache: {err}")))
+        })
+        .await
+    }
+}
+
+/// Start the refresh cooldown of `user_id` at `now`, unless it's still
+/// running, in which case when it ends is returned instead.
+///
+/// The cooldown is claimed with a compare-and-swap, so of concurrent
+/// refreshes only one gets it.
+#[cfg(feature = "ssr")]
+fn claim_refresh(store: &dyn Store, user_id: &str, now: i64) -> Result<Option<i64>, store::Error> {
+    let key = user_key(user_id, "refreshed");
+    let cooldown = crate::config::get().cache.refresh_cooldown.whole_seconds();
+    let current = store.get(DEFAULT_TREE, &key)?;
+
+    let available_at = current
+        .as_deref()
+        .and_then(|bytes| store::schema::decode_or_log::<i64>(DEFAULT_TREE, &key, bytes))
+        .map(|refreshed| refreshed + cooldown)
+        .filter(|available_at| *available_at > now);
+
+    if available_at.is_some() {
+        return Ok(available_at);
+    }
+
+    match store.compare_and_swap(DEFAULT_TREE, &key, current.as_deref(), Some(store::schema::encode(&now)))? {
+        true => Ok(None),
+        // another refresh claimed it first
+        false => Ok(Some(now + cooldown)),
+    }
+}
+
+#[cfg(feature = "ssr")]
+async fn refresh_status(store: &dyn Store, user_id: &str) -> Result<RefreshStatus, store::Error> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    let available_at = get_from_db::<i64>(store, &user_key(user_id, "refreshed"))
+        .await?
+        .map(|refreshed| refreshed + crate::config::get().cache.refresh_cooldown.whole_seconds())
+        .filter(|available_at| *available_at > now);
+
+    Ok(RefreshStatus {
+        refreshed_at: cache_fetched_at(store, &user_key(user_id, "userinfo")).await?,
+        available_at,
+    })
+}
+
+/// Log out and remove everything stored about the current user.
+#[server]
+pub async fn delete_my_data() -> Result<(), ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        let mut auth_session = use_context::<AuthSession>()
+            .expect("no auth session provided");
+        let store = use_context::<SharedStore>().expect("no store provided");
+
+        let Some(user) = auth_session.user.clone() else {
+            return Ok(());
+        };
+
+        auth_session
+            .logout()
+            .map_err(|err| ServerFnError::ServerError(format!("Error logging out: {err}")))?;
+
+        delete_user_data(&*store, &user.user_id)
+            .await
+            .map_err(|err| ServerFnError::ServerError(format!("Error deleting data: {err}")))
+    }
+}
@@ -0,0 +1,486 @@
+use std::{
+    env, fmt,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+use time::Duration;
+
+/// Environment variable pointing at the configuration file.
+pub const CONFIG_PATH_VAR: &str = "STARIFY_CONFIG";
+
+/// Configuration file read when [`CONFIG_PATH_VAR`] isn't set. It's fine for it not to exist.
+pub const DEFAULT_CONFIG_PATH: &str = "starify.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// The server configuration set with [`init`].
+///
+/// # Panics
+/// If called before [`init`].
+pub fn get() -> &'static Config {
+    CONFIG.get().expect("configuration not initialized")
+}
+
+/// Set the configuration returned by [`get`]. Only the first call has an effect.
+pub fn init(config: Config) -> &'static Config {
+    CONFIG.get_or_init(|| config)
+}
+
+/// Validated server configuration.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub spotify: SpotifyConfig,
+    /// Address the server listens on.
+    pub bind_address: SocketAddr,
+    /// Origin users reach the server at, without a trailing slash, e.g. `https://starify.example.com`.
+    pub public_url: String,
+    /// Reverse proxies whose `X-Forwarded-Proto` and `X-Forwarded-Host` headers are believed.
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Domain the session cookie is shared with, e.g. `example.com` for every
+    /// subdomain. Cookies are only sent to the host that set them without one.
+    pub cookie_domain: Option<String>,
+    /// Directory of the sled database.
+    pub database_path: PathBuf,
+    /// Secret used to sign OAuth2 states, if any.
+    pub state_secret: Option<Vec<u8>>,
+    /// Keys stored Spotify tokens are encrypted with. The first one encrypts,
+    /// the rest only decrypt tokens from before a rotation. Tokens are stored
+    /// unencrypted if there are none.
+    pub token_keys: Vec<TokenKey>,
+    /// How long an inactive session stays logged in.
+    pub session_expiry: Duration,
+    pub cache: CacheConfig,
+}
+
+#[derive(Clone)]
+pub struct SpotifyConfig {
+    pub client_id: String,
+    /// Only needed by [`AuthFlow::AuthCode`].
+    pub client_secret: Option<String>,
+    pub auth_flow: AuthFlow,
+    /// Base URL of the Web API, only changed to test against a stand-in.
+    pub api_base_url: String,
+    /// Base URL of the accounts service, only changed to test against a stand-in.
+    pub auth_base_url: String,
+}
+
+impl fmt::Debug for SpotifyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpotifyConfig")
+            .field("client_id", &self.client_id)
+            .field("auth_flow", &self.auth_flow)
+            .field("api_base_url", &self.api_base_url)
+            .field("auth_base_url", &self.auth_base_url)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A key for encrypting stored Spotify tokens.
+#[derive(Clone)]
+pub struct TokenKey {
+    /// Stored with every token, so the key it was encrypted with can be found after a rotation.
+    pub id: String,
+    pub key: [u8; 32],
+}
+
+impl fmt::Debug for TokenKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenKey")
+            .field("id", &self.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromStr for TokenKey {
+    type Err = String;
+
+    /// Parse `{id}:{key}`, with the key as 32 bytes of base64.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+
+        let Some((id, key)) = value.split_once(':') else {
+            return Err("must look like `{id}:{base64 key}`".to_string());
+        };
+
+        if id.is_empty() || id.len() > u8::MAX as usize {
+            return Err(format!("key ID {id:?} must be 1 to 255 bytes long"));
+        }
+
+        let key = STANDARD
+            .decode(key.trim())
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .ok_or_else(|| format!("key {id:?} must be 32 bytes of base64"))?;
+
+        Ok(Self { id: id.to_string(), key })
+    }
+}
+
+/// How users log in with Spotify.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthFlow {
+    /// The authorization code flow, which needs the client secret.
+    AuthCode,
+    /// The authorization code flow with PKCE, which only needs the client ID.
+    Pkce,
+}
+
+impl FromStr for AuthFlow {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "auth_code" => Ok(Self::AuthCode),
+            "pkce" => Ok(Self::Pkce),
+            _ => Err(format!("{value:?} is not `auth_code` or `pkce`")),
+        }
+    }
+}
+
+/// How long cached Spotify responses are kept.
+#[derive(Clone, Debug)]
+pub struct CacheConfig {
+    pub userinfo_ttl: Duration,
+    pub short_term_ttl: Duration,
+    pub medium_term_ttl: Duration,
+    pub long_term_ttl: Duration,
+    /// How long a user has to wait between manual refreshes.
+    pub refresh_cooldown: Duration,
+}
+
+/// The longest expiry or TTL accepted, so adding it to a date can't overflow.
+const MAX_DURATION_SECS: i64 = 10 * 365 * 24 * 60 * 60;
+
+/// A problem with a single setting.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("could not read {path}: {err}")]
+    Read { path: PathBuf, err: std::io::Error },
+
+    #[error("could not parse {path}: {err}")]
+    Parse { path: PathBuf, err: toml::de::Error },
+
+    #[error("`{key}` is required (or set {var})")]
+    Missing { key: &'static str, var: &'static str },
+
+    #[error("`{key}` is invalid: {message}")]
+    Invalid { key: &'static str, message: String },
+
+    #[error("{var}={value:?} is invalid: {message}")]
+    InvalidVar { var: &'static str, value: String, message: String },
+}
+
+/// Every problem found while loading the configuration.
+#[derive(Debug, Error)]
+pub struct Error(pub Vec<ConfigError>);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "invalid configuration:")?;
+
+        for err in &self.0 {
+            writeln!(f, "  - {err}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The configuration file as written, before environment overrides and validation.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawConfig {
+    spotify: RawSpotifyConfig,
+    bind_address: Option<String>,
+    public_url: Option<String>,
+    trusted_proxies: Option<Vec<String>>,
+    cookie_domain: Option<String>,
+    database_path: Option<PathBuf>,
+    state_secret: Option<String>,
+    token_keys: Option<Vec<String>>,
+    session_expiry_secs: Option<i64>,
+    cache: RawCacheConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawSpotifyConfig {
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    auth_flow: Option<String>,
+    api_base_url: Option<String>,
+    auth_base_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RawCacheConfig {
+    userinfo_ttl_secs: Option<i64>,
+    short_term_ttl_secs: Option<i64>,
+    medium_term_ttl_secs: Option<i64>,
+    long_term_ttl_secs: Option<i64>,
+    refresh_cooldown_secs: Option<i64>,
+}
+
+impl Config {
+    /// Load the file at [`CONFIG_PATH_VAR`] (or [`DEFAULT_CONFIG_PATH`]),
+    /// apply environment overrides and validate the result.
+    ///
+    /// `default_bind_address` is used when no bind address is configured.
+    pub fn load(default_bind_address: SocketAddr) -> Result<Self, Error> {
+        let (path, required) = match env::var(CONFIG_PATH_VAR) {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+        };
+
+        let mut raw = match read_file(&path, required) {
+            Ok(raw) => raw,
+            Err(err) => return Err(Error(vec![err])),
+        };
+
+        let mut errors = raw.apply_env();
+
+        match raw.validate(default_bind_address) {
+            Ok(config) if errors.is_empty() => Ok(config),
+            Ok(_) => Err(Error(errors)),
+            Err(Error(invalid)) => {
+                errors.extend(invalid);
+                Err(Error(errors))
+            }
+        }
+    }
+}
+
+fn read_file(path: &Path, required: bool) -> Result<RawConfig, ConfigError> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => toml::from_str(&contents).map_err(|err| ConfigError::Parse {
+            path: path.to_owned(),
+            err,
+        }),
+        Err(err) if !required && err.kind() == std::io::ErrorKind::NotFound => Ok(RawConfig::default()),
+        Err(err) => Err(ConfigError::Read {
+            path: path.to_owned(),
+            err,
+        }),
+    }
+}
+
+impl RawConfig {
+    /// Environment variables take precedence over the file. Returns the
+    /// variables that couldn't be parsed, which are ignored.
+    fn apply_env(&mut self) -> Vec<ConfigError> {
+        let mut errors = Vec::new();
+
+        let var = |name: &str| env::var(name).ok();
+        let mut secs = |name: &'static str| {
+            var(name).and_then(|value| match value.trim().parse() {
+                Ok(secs) => Some(secs),
+                Err(err) => {
+                    errors.push(ConfigError::InvalidVar {
+                        var: name,
+                        message: format!("must be a whole number of seconds ({err})"),
+                        value,
+                    });
+                    None
+                }
+            })
+        };
+
+        override_with(&mut self.spotify.client_id, var("SPOTIFY_CLIENT_ID"));
+        override_with(&mut self.spotify.client_secret, var("SPOTIFY_CLIENT_SECRET"));
+        override_with(&mut self.spotify.auth_flow, var("STARIFY_SPOTIFY_AUTH_FLOW"));
+        override_with(&mut self.spotify.api_base_url, var("STARIFY_SPOTIFY_API_URL"));
+        override_with(&mut self.spotify.auth_base_url, var("STARIFY_SPOTIFY_AUTH_URL"));
+        override_with(&mut self.bind_address, var("STARIFY_SOCKET"));
+        override_with(&mut self.public_url, var("STARIFY_PUBLIC_URL"));
+        override_with(
+            &mut self.trusted_proxies,
+            var("STARIFY_TRUSTED_PROXIES").map(|proxies| proxies.split(',').map(|proxy| proxy.trim().to_string()).collect()),
+        );
+        override_with(&mut self.cookie_domain, var("STARIFY_COOKIE_DOMAIN"));
+        override_with(&mut self.database_path, var("STARIFY_CACHE").map(PathBuf::from));
+        override_with(&mut self.state_secret, var("STARIFY_STATE_SECRET"));
+        override_with(
+            &mut self.token_keys,
+            var("STARIFY_TOKEN_KEYS").map(|keys| keys.split(',').map(|key| key.trim().to_string()).collect()),
+        );
+        override_with(&mut self.session_expiry_secs, secs("STARIFY_SESSION_EXPIRY_SECS"));
+        override_with(&mut self.cache.userinfo_ttl_secs, secs("STARIFY_USERINFO_TTL_SECS"));
+        override_with(&mut self.cache.short_term_ttl_secs, secs("STARIFY_SHORT_TERM_TTL_SECS"));
+        override_with(&mut self.cache.medium_term_ttl_secs, secs("STARIFY_MEDIUM_TERM_TTL_SECS"));
+        override_with(&mut self.cache.long_term_ttl_secs, secs("STARIFY_LONG_TERM_TTL_SECS"));
+        override_with(&mut self.cache.refresh_cooldown_secs, secs("STARIFY_REFRESH_COOLDOWN_SECS"));
+
+        errors
+    }
+
+    fn validate(self, default_bind_address: SocketAddr) -> Result<Config, Error> {
+        let mut errors = Vec::new();
+
+        let client_id = required(&mut errors, "spotify.client_id", "SPOTIFY_CLIENT_ID", self.spotify.client_id);
+        let client_secret = self.spotify.client_secret.filter(|secret| !secret.is_empty());
+
+        // without a secret PKCE is the only flow that can work
+        let auth_flow = match self.spotify.auth_flow {
+            Some(flow) => AuthFlow::from_str(&flow).unwrap_or_else(|message| {
+                errors.push(ConfigError::Invalid { key: "spotify.auth_flow", message });
+                AuthFlow::AuthCode
+            }),
+            None if client_secret.is_some() => AuthFlow::AuthCode,
+            None => AuthFlow::Pkce,
+        };
+
+        if auth_flow == AuthFlow::AuthCode && client_secret.is_none() {
+            errors.push(ConfigError::Missing {
+                key: "spotify.client_secret",
+                var: "SPOTIFY_CLIENT_SECRET",
+            });
+        }
+
+        let bind_address = match self.bind_address {
+            Some(address) => SocketAddr::from_str(&address).unwrap_or_else(|err| {
+                errors.push(ConfigError::Invalid {
+                    key: "bind_address",
+                    message: format!("{address:?} is not a socket address ({err})"),
+                });
+                default_bind_address
+            }),
+            None => default_bind_address,
+        };
+
+        let public_url = match self.public_url {
+            Some(url) => {
+                let url = url.trim_end_matches('/').to_string();
+
+                if !(url.starts_with("http://") || url.starts_with("https://")) {
+                    errors.push(ConfigError::Invalid {
+                        key: "public_url",
+                        message: format!("{url:?} must start with http:// or https://"),
+                    });
+                }
+
+                url
+            }
+            None => format!("http://{bind_address}"),
+        };
+
+        let trusted_proxies = self
+            .trusted_proxies
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|proxy| match IpAddr::from_str(&proxy) {
+                Ok(ip) => Some(ip),
+                Err(err) => {
+                    errors.push(ConfigError::Invalid {
+                        key: "trusted_proxies",
+                        message: format!("{proxy:?} is not an IP address ({err})"),
+                    });
+                    None
+                }
+            })
+            .collect();
+
+        let cookie_domain = self.cookie_domain.filter(|domain| !domain.is_empty());
+
+        if let Some(domain) = cookie_domain.as_ref().filter(|domain| domain.contains([':', '/'])) {
+            errors.push(ConfigError::Invalid {
+                key: "cookie_domain",
+                message: format!("{domain:?} must be a domain, without scheme or port"),
+            });
+        }
+
+        let state_secret = self.state_secret.map(String::into_bytes);
+
+        if state_secret.as_ref().is_some_and(|secret| secret.len() < 32) {
+            errors.push(ConfigError::Invalid {
+                key: "state_secret",
+                message: "must be at least 32 bytes long".to_string(),
+            });
+        }
+
+        let mut token_keys: Vec<TokenKey> = Vec::new();
+
+        for key in self.token_keys.unwrap_or_default() {
+            match TokenKey::from_str(&key) {
+                Ok(key) if token_keys.iter().any(|other| other.id == key.id) => errors.push(ConfigError::Invalid {
+                    key: "token_keys",
+                    message: format!("key ID {:?} is used twice", key.id),
+                }),
+                Ok(key) => token_keys.push(key),
+                Err(message) => errors.push(ConfigError::Invalid { key: "token_keys", message }),
+            }
+        }
+
+        let mut duration = |key, secs: Option<i64>, default| match secs {
+            Some(secs) if secs > 0 && secs <= MAX_DURATION_SECS => Duration::seconds(secs),
+            Some(secs) => {
+                let message = match secs > 0 {
+                    true => format!("must be at most {MAX_DURATION_SECS} seconds (ten years)"),
+                    false => "must be a positive number of seconds".to_string(),
+                };
+                errors.push(ConfigError::Invalid { key, message });
+                default
+            }
+            None => default,
+        };
+
+        let session_expiry = duration("session_expiry_secs", self.session_expiry_secs, Duration::days(1));
+
+        let cache = CacheConfig {
+            userinfo_ttl: duration("cache.userinfo_ttl_secs", self.cache.userinfo_ttl_secs, Duration::days(1)),
+            short_term_ttl: duration("cache.short_term_ttl_secs", self.cache.short_term_ttl_secs, Duration::hours(6)),
+            medium_term_ttl: duration("cache.medium_term_ttl_secs", self.cache.medium_term_ttl_secs, Duration::days(1)),
+            long_term_ttl: duration("cache.long_term_ttl_secs", self.cache.long_term_ttl_secs, Duration::weeks(1)),
+            refresh_cooldown: duration("cache.refresh_cooldown_secs", self.cache.refresh_cooldown_secs, Duration::minutes(10)),
+        };
+
+        if !errors.is_empty() {
+            return Err(Error(errors));
+        }
+
+        Ok(Config {
+            spotify: SpotifyConfig {
+                client_id,
+                client_secret,
+                auth_flow,
+                api_base_url: self.spotify.api_base_url.unwrap_or_else(|| rspotify::DEFAULT_API_BASE_URL.to_string()),
+                auth_base_url: self.spotify.auth_base_url.unwrap_or_else(|| rspotify::DEFAULT_AUTH_BASE_URL.to_string()),
+            },
+            bind_address,
+            public_url,
+            trusted_proxies,
+            cookie_domain,
+            database_path: self.database_path.unwrap_or_else(|| PathBuf::from("starify_cache")),
+            state_secret,
+            token_keys,
+            session_expiry,
+            cache,
+        })
+    }
+}
+
+fn override_with<T>(value: &mut Option<T>, env: Option<T>) {
+    if env.is_some() {
+        *value = env;
+    }
+}
+
+fn required(
+    errors: &mut Vec<ConfigError>,
+    key: &'static str,
+    var: &'static str,
+    value: Option<String>,
+) -> String {
+    match value {
+        Some(value) if !value.is_empty() => value,
+        _ => {
+            errors.push(ConfigError::Missing { key, var });
+            String::new()
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::{HashMap, HashSet};
+
+use leptos::*;
+use rspotify::model::{FullArtist, TimeRange};
+use serde::{Deserialize, Serialize};
+
+use crate::client::TopTrack;
+
+pub mod genres;
+pub mod layout;
+
+/// An artist placed in a [`Constellation`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ArtistNode {
+    pub id: String,
+    pub name: String,
+    pub genres: Vec<String>,
+    pub image: Option<String>,
+    pub popularity: u32,
+    /// Position in the user's top artists, starting at 0.
+    pub rank: usize,
+}
+
+impl From<(usize, &FullArtist)> for ArtistNode {
+    fn from((rank, artist): (usize, &FullArtist)) -> Self {
+        Self {
+            id: artist.id.to_string(),
+            name: artist.name.clone(),
+            genres: artist.genres.clone(),
+            image: artist.images.first().map(|image| image.url.clone()),
+            popularity: artist.popularity,
+            rank,
+        }
+    }
+}
+
+/// Why two artists are connected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EdgeKind {
+    /// Spotify lists one artist as related to the other.
+    Related,
+    /// Both artists share at least one genre.
+    SharedGenre,
+    /// Both artists are credited on one of the user's top tracks.
+    SharedTrack,
+}
+
+/// An undirected link between two [`ArtistNode`]s, stored as indices into
+/// [`Constellation::nodes`] with `source < target`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Edge {
+    pub source: usize,
+    pub target: usize,
+    /// Strength of the connection in `(0, 1]`.
+    pub weight: f32,
+    pub kind: EdgeKind,
+}
+
+/// A graph of a user's top artists and the links between them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Constellation {
+    pub nodes: Vec<ArtistNode>,
+    pub edges: Vec<Edge>,
+}
+
+impl Constellation {
+    /// Build a constellation from the user's top artists, in rank order, the
+    /// Spotify related artists of each one (keyed by artist ID) and the user's top tracks.
+    pub fn build(top: &[FullArtist], related: &HashMap<String, Vec<FullArtist>>, tracks: &[TopTrack]) -> Self {
+        let nodes: Vec<ArtistNode> = top.iter().enumerate().map(ArtistNode::from).collect();
+
+        let index: HashMap<&str, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.as_str(), i))
+            .collect();
+
+        // related artists are listed most-related first, so earlier positions weigh more
+        let mut related_weights: HashMap<(usize, usize), f32> = HashMap::new();
+
+        for (source, node) in nodes.iter().enumerate() {
+            let Some(list) = related.get(&node.id) else {
+                continue;
+            };
+
+            for (position, artist) in list.iter().enumerate() {
+                let Some(&target) = index.get(artist.id.to_string().as_str()) else {
+                    continue;
+                };
+
+                if target == source {
+                    continue;
+                }
+
+                let weight = 1.0 - position as f32 / list.len() as f32;
+                let pair = (source.min(target), source.max(target));
+                let entry = related_weights.entry(pair).or_default();
+                *entry = entry.max(weight);
+            }
+        }
+
+        let mut edges: Vec<Edge> = related_weights
+            .into_iter()
+            .map(|((source, target), weight)| Edge {
+                source,
+                target,
+                weight,
+                kind: EdgeKind::Related,
+            })
+            .collect();
+
+        // shared genres are weighted by the jaccard index of both genre sets
+        let genres: Vec<HashSet<&str>> = nodes
+            .iter()
+            .map(|node| node.genres.iter().map(String::as_str).collect())
+            .collect();
+
+        for source in 0..nodes.len() {
+            for target in (source + 1)..nodes.len() {
+                let shared = genres[source].intersection(&genres[target]).count();
+
+                if shared == 0 {
+                    continue;
+                }
+
+                let total = genres[source].union(&genres[target]).count();
+
+                edges.push(Edge {
+                    source,
+                    target,
+                    weight: shared as f32 / total as f32,
+                    kind: EdgeKind::SharedGenre,
+                });
+            }
+        }
+
+        // artists appearing together are weighted by the jaccard index of the tracks they're on
+        let mut artist_tracks: Vec<HashSet<usize>> = vec![HashSet::new(); nodes.len()];
+
+        for (position, track) in tracks.iter().enumerate() {
+            for artist in &track.artists {
+                if let Some(&i) = artist.id.as_deref().and_then(|id| index.get(id)) {
+                    artist_tracks[i].insert(position);
+                }
+            }
+        }
+
+        for source in 0..nodes.len() {
+            for target in (source + 1)..nodes.len() {
+                let shared = artist_tracks[source].intersection(&artist_tracks[target]).count();
+
+                if shared == 0 {
+                    continue;
+                }
+
+                let total = artist_tracks[source].union(&artist_tracks[target]).count();
+
+                edges.push(Edge {
+                    source,
+                    target,
+                    weight: shared as f32 / total as f32,
+                    kind: EdgeKind::SharedTrack,
+                });
+            }
+        }
+
+        // keep the output stable regardless of hash map iteration order
+        edges.sort_by(|a, b| {
+            (a.source, a.target, a.kind as u8).cmp(&(b.source, b.target, b.kind as u8))
+        });
+
+        Self { nodes, edges }
+    }
+}
+
+#[server]
+pub async fn get_constellation(range: TimeRange) -> Result<Option<Constellation>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        let Some(user) = use_context::<crate::auth::AuthSession>()
+            .expect("no auth session provided")
+            .user else {
+                return Ok(None);
+            };
+
+        let store = use_context::<crate::store::SharedStore>().expect("no store provided");
+
+        constellation(&*store, &user, range).await.map(Some)
+    }
+}
+
+#[cfg(feature = "ssr")]
+impl crate::store::Stored for Constellation {
+    const TAG: &'static str = "constellation";
+}
+
+/// How many related artists requests [`constellation`] makes at once.
+#[cfg(feature = "ssr")]
+const RELATED_ARTISTS_CONCURRENCY: usize = 8;
+
+/// The constellation of `user` for `range`, from the cache if possible.
+#[cfg(feature = "ssr")]
+pub async fn constellation(store: &dyn crate::store::Store, user: &crate::auth::User, range: TimeRange) -> Result<Constellation, ServerFnError> {
+    use crate::client::{get_or_fetch, range_ttl, top_artists, top_tracks};
+    use futures::StreamExt;
+
+    let constellation_key = crate::store::user_key(&user.user_id, &format!("constellation_{range:?}"));
+
+    get_or_fetch(store, &constellation_key, range_ttl(range), || async {
+        let top = top_artists(store, user, range).await?;
+
+        // collected, as a stream over the mapping closure can't be proven Send
+        let requests: Vec<_> = top.iter().map(|artist| related_artists(&user.client, artist.id.clone())).collect();
+
+        // only a few requests at a time, so large libraries don't get rate limited
+        let related = futures::stream::iter(requests)
+            .buffer_unordered(RELATED_ARTISTS_CONCURRENCY)
+            .collect()
+            .await;
+
+        // like related artists, missing tracks only remove edges
+        let tracks = top_tracks(store, user, range).await.unwrap_or_else(|err| {
+            tracing::warn!("Error fetching top tracks: {err}");
+            Vec::new()
+        });
+
+        Ok(Constellation::build(&top, &related, &tracks))
+    })
+    .await
+}
+
+/// The artists related to `id`, keyed by its ID for [`Constellation::build`].
+///
+/// A missing related list only removes edges, so errors don't fail the whole graph.
+#[cfg(feature = "ssr")]
+async fn related_artists(client: &crate::auth::SpotifyClient, id: rspotify::model::ArtistId<'static>) -> (String, Vec<FullArtist>) {
+    use rspotify::clients::BaseClient;
+
+    match client.artist_related_artists(id.clone()).await {
+        Ok(related) => (id.to_string(), related),
+        Err(err) => {
+            tracing::warn!("Error fetching related artists for {id}: {err}");
+            (id.to_string(), Vec::new())
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Groups a user's top artists by genre.
+//!
+//! Artists are weighted by rank, from 1 for the top artist down to `1 / n`
+//! for the last of `n`, so a genre shared by a few favourites outweighs one
+//! shared by many artists the user barely listens to.
+
+use std::collections::HashMap;
+
+use leptos::*;
+use rspotify::model::TimeRange;
+use serde::{Deserialize, Serialize};
+
+use super::ArtistNode;
+
+/// Most clusters [`clusters`] returns, so the star map stays readable.
+pub const MAX_CLUSTERS: usize = 8;
+
+/// Fewest artists that make up a cluster.
+pub const MIN_CLUSTER_SIZE: usize = 2;
+
+/// Most genres [`breakdown`] returns.
+pub const MAX_BREAKDOWN_GENRES: usize = 10;
+
+/// Artists grouped under the genre they have in common.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GenreCluster {
+    /// The genre every member has.
+    pub name: String,
+    /// Indices into the nodes the cluster was built from, best ranked first.
+    pub members: Vec<usize>,
+    /// Share of the user's listening the members make up, in `(0, 1]`.
+    pub weight: f32,
+}
+
+/// How much of the user's listening a genre makes up.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GenreShare {
+    pub genre: String,
+    /// Share of the rank weight of all artists with this genre, in `(0, 1]`.
+    /// Artists have several genres, so shares don't add up to 1.
+    pub share: f32,
+    /// Number of artists with this genre.
+    pub artists: usize,
+}
+
+/// The top genres of one [`TimeRange`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RangeGenres {
+    pub range: TimeRange,
+    pub genres: Vec<GenreShare>,
+}
+
+/// Weight of an artist at `rank` among `count` artists.
+fn rank_weight(rank: usize, count: usize) -> f32 {
+    (count - rank.min(count - 1)) as f32 / count as f32
+}
+
+/// Greedily group `nodes` into genre clusters, strongest first.
+///
+/// The genre with the most weight among the artists not yet in a cluster
+/// becomes the next cluster, taking every remaining artist with that genre.
+/// This stops at [`MAX_CLUSTERS`] or once no genre is shared by
+/// [`MIN_CLUSTER_SIZE`] remaining artists, leaving the rest unclustered.
+pub fn clusters(nodes: &[ArtistNode]) -> Vec<GenreCluster> {
+    let weights: Vec<f32> = nodes
+        .iter()
+        .map(|node| rank_weight(node.rank, nodes.len()))
+        .collect();
+    let total: f32 = weights.iter().sum();
+
+    let mut clustered = vec![false; nodes.len()];
+    let mut clusters = Vec::new();
+
+    while clusters.len() < MAX_CLUSTERS {
+        let mut scores: HashMap<&str, (f32, usize)> = HashMap::new();
+
+        for (i, node) in nodes.iter().enumerate().filter(|(i, _)| !clustered[*i]) {
+            for genre in &node.genres {
+                let score = scores.entry(genre.as_str()).or_default();
+                score.0 += weights[i];
+                score.1 += 1;
+            }
+        }
+
+        // ties go to the alphabetically first genre, so the result is stable
+        let best = scores
+            .into_iter()
+            .filter(|(_, (_, count))| *count >= MIN_CLUSTER_SIZE)
+            .max_by(|(a_genre, (a, _)), (b_genre, (b, _))| a.total_cmp(b).then_with(|| b_genre.cmp(a_genre)));
+
+        let Some((genre, (score, _))) = best else {
+            break;
+        };
+
+        let mut members: Vec<usize> = (0..nodes.len())
+            .filter(|&i| !clustered[i] && nodes[i].genres.iter().any(|g| g == genre))
+            .collect();
+
+        members.sort_by_key(|&i| nodes[i].rank);
+        members.iter().for_each(|&i| clustered[i] = true);
+
+        clusters.push(GenreCluster {
+            name: genre.to_string(),
+            members,
+            weight: score / total,
+        });
+    }
+
+    clusters
+}
+
+/// The [`MAX_BREAKDOWN_GENRES`] genres with the biggest share of `nodes`.
+pub fn breakdown(nodes: &[ArtistNode]) -> Vec<GenreShare> {
+    let total: f32 = nodes
+        .iter()
+        .map(|node| rank_weight(node.rank, nodes.len()))
+        .sum();
+
+    let mut scores: HashMap<&str, (f32, usize)> = HashMap::new();
+
+    for node in nodes {
+        for genre in &node.genres {
+            let score = scores.entry(genre.as_str()).or_default();
+            score.0 += rank_weight(node.rank, nodes.len());
+            score.1 += 1;
+        }
+    }
+
+    let mut shares: Vec<GenreShare> = scores
+        .into_iter()
+        .map(|(genre, (score, artists))| GenreShare {
+            genre: genre.to_string(),
+            share: score / total,
+            artists,
+        })
+        .collect();
+
+    shares.sort_by(|a, b| b.share.total_cmp(&a.share).then_with(|| a.genre.cmp(&b.genre)));
+    shares.truncate(MAX_BREAKDOWN_GENRES);
+
+    shares
+}
+
+/// The top genres of the current user for every [`TimeRange`].
+#[server]
+pub async fn get_genre_breakdown() -> Result<Option<Vec<RangeGenres>>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        crate::server::with_request_runtime(async move {
+            use crate::client::get_top_artists;
+
+            let mut ranges = Vec::new();
+
+            for range in [TimeRange::ShortTerm, TimeRange::MediumTerm, TimeRange::LongTerm] {
+                let Some(top) = get_top_artists(range).await? else {
+                    return Ok(None);
+                };
+
+                let nodes: Vec<ArtistNode> = top.iter().enumerate().map(ArtistNode::from).collect();
+
+                ranges.push(RangeGenres {
+                    range,
+                    genres: breakdown(&nodes),
+                });
+            }
+
+            Ok(Some(ranges))
+        })
+        .await
+    }
+}
@@ -0,0 +1,235 @@
+//! A seeded Fruchterman–Reingold layout for [`Constellation`]s.
+//!
+//! Everything here sticks to `f64` arithmetic and `sqrt`, which are exactly
+//! specified by IEEE 754, and a hand-rolled PRNG so the server and the
+//! hydrated client place every star at the same coordinates.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    genres::{self, GenreCluster},
+    ArtistNode, Constellation, EdgeKind,
+};
+
+/// How strongly artists in the same genre cluster are pulled to its center,
+/// relative to an edge of weight 1.
+const CLUSTER_PULL: f64 = 0.3;
+
+/// Parameters for [`layout`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayoutOptions {
+    pub seed: u64,
+    pub iterations: usize,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            seed: 0x57A2_1F1E,
+            iterations: 300,
+            width: 1000.0,
+            height: 1000.0,
+        }
+    }
+}
+
+/// A position in layout space, with `(0, 0)` at the top left.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Place every node of `constellation` inside `options.width` by
+/// `options.height`, returning one [`Point`] per node in the same order.
+///
+/// More popular artists are heavier and move less, heavier edges pull
+/// their artists closer together, and artists in the same
+/// [`genres::clusters`] drift towards each other so every cluster gets its own region.
+pub fn layout(constellation: &Constellation, options: &LayoutOptions) -> Vec<Point> {
+    let count = constellation.nodes.len();
+
+    if count < 2 {
+        let center = Point {
+            x: options.width / 2.0,
+            y: options.height / 2.0,
+        };
+
+        return vec![center; count];
+    }
+
+    let mut rng = SplitMix64(options.seed);
+
+    let mut positions: Vec<Point> = (0..count)
+        .map(|_| Point {
+            x: rng.next_f64() * options.width,
+            y: rng.next_f64() * options.height,
+        })
+        .collect();
+
+    let masses: Vec<f64> = constellation
+        .nodes
+        .iter()
+        .map(|node| 1.0 + node.popularity as f64 / 100.0)
+        .collect();
+
+    // ideal distance between two nodes
+    let k = (options.width * options.height / count as f64).sqrt();
+    let initial_temperature = options.width.min(options.height) / 10.0;
+
+    let clusters = genres::clusters(&constellation.nodes);
+
+    let mut displacements = vec![Point::default(); count];
+
+    for iteration in 0..options.iterations {
+        displacements.iter_mut().for_each(|d| *d = Point::default());
+
+        // every pair of nodes pushes each other apart
+        for i in 0..count {
+            for j in (i + 1)..count {
+                let (dx, dy, distance) = delta(positions[i], positions[j]);
+                let force = k * k / distance;
+
+                displacements[i].x += dx / distance * force;
+                displacements[i].y += dy / distance * force;
+                displacements[j].x -= dx / distance * force;
+                displacements[j].y -= dy / distance * force;
+            }
+        }
+
+        // connected nodes pull each other together
+        for edge in &constellation.edges {
+            let (dx, dy, distance) = delta(positions[edge.source], positions[edge.target]);
+            let force = distance * distance / k * edge.weight as f64;
+
+            displacements[edge.source].x -= dx / distance * force;
+            displacements[edge.source].y -= dy / distance * force;
+            displacements[edge.target].x += dx / distance * force;
+            displacements[edge.target].y += dy / distance * force;
+        }
+
+        // cluster members pull towards their cluster's center
+        for cluster in &clusters {
+            let center = centroid(cluster.members.iter().map(|&i| positions[i]));
+
+            for &i in &cluster.members {
+                let (dx, dy, distance) = delta(positions[i], center);
+                let force = distance * distance / k * CLUSTER_PULL;
+
+                displacements[i].x -= dx / distance * force;
+                displacements[i].y -= dy / distance * force;
+            }
+        }
+
+        // cool down linearly so the layout settles
+        let temperature =
+            initial_temperature * (1.0 - iteration as f64 / options.iterations as f64);
+
+        for i in 0..count {
+            let dx = displacements[i].x / masses[i];
+            let dy = displacements[i].y / masses[i];
+            let length = (dx * dx + dy * dy).sqrt();
+
+            if length > 0.0 {
+                let step = length.min(temperature);
+
+                positions[i].x = (positions[i].x + dx / length * step).clamp(0.0, options.width);
+                positions[i].y = (positions[i].y + dy / length * step).clamp(0.0, options.height);
+            }
+        }
+    }
+
+    positions
+}
+
+// How the map is drawn, shared by the dashboard and exports so both look the same.
+
+/// Star radius in layout units, from 3 for unknown artists to 12 for the most popular.
+pub fn star_radius(node: &ArtistNode) -> f64 {
+    3.0 + 9.0 * node.popularity.min(100) as f64 / 100.0
+}
+
+/// Star opacity, fading from 1 for the top artist to 0.3 for the last of `count`.
+pub fn star_brightness(node: &ArtistNode, count: usize) -> f64 {
+    1.0 - 0.7 * node.rank as f64 / count.max(1) as f64
+}
+
+/// Dash and gap length of an edge's line, or `None` if it's solid.
+pub fn edge_dash(kind: EdgeKind) -> Option<(f64, f64)> {
+    match kind {
+        EdgeKind::Related => None,
+        EdgeKind::SharedGenre => Some((4.0, 6.0)),
+        EdgeKind::SharedTrack => Some((1.0, 4.0)),
+    }
+}
+
+/// Center and radius of the circle around a genre cluster's stars.
+pub fn cluster_region(cluster: &GenreCluster, nodes: &[ArtistNode], positions: &[Point]) -> (Point, f64) {
+    let center = centroid(cluster.members.iter().map(|&i| positions[i]));
+    let radius = cluster
+        .members
+        .iter()
+        .map(|&i| {
+            let (dx, dy) = (positions[i].x - center.x, positions[i].y - center.y);
+            (dx * dx + dy * dy).sqrt() + star_radius(&nodes[i])
+        })
+        .fold(0.0, f64::max)
+        + 20.0;
+
+    (center, radius)
+}
+
+/// The average of `points`, or the origin if there are none.
+pub fn centroid(points: impl IntoIterator<Item = Point>) -> Point {
+    let (sum, count) = points
+        .into_iter()
+        .fold((Point::default(), 0), |(sum, count), point| {
+            (Point { x: sum.x + point.x, y: sum.y + point.y }, count + 1)
+        });
+
+    if count == 0 {
+        return sum;
+    }
+
+    Point {
+        x: sum.x / count as f64,
+        y: sum.y / count as f64,
+    }
+}
+
+/// The vector from `b` to `a` and its length, kept away from zero so
+/// overlapping nodes still push apart.
+fn delta(a: Point, b: Point) -> (f64, f64, f64) {
+    let mut dx = a.x - b.x;
+    let mut dy = a.y - b.y;
+
+    if dx == 0.0 && dy == 0.0 {
+        dx = 0.01;
+        dy = 0.01;
+    }
+
+    (dx, dy, (dx * dx + dy * dy).sqrt().max(0.01))
+}
+
+/// A small PRNG whose output doesn't depend on the platform.
+///
+/// See <https://prng.di.unimi.it/splitmix64.c>.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A uniformly distributed value in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
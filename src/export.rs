@@ -0,0 +1,187 @@
+//! Downloads of a user's data, served at [`crate::EXPORT_ENDPOINT`].
+
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use leptos::ServerFnError;
+use rspotify::model::TimeRange;
+use thiserror::Error;
+use time::OffsetDateTime;
+
+use crate::{auth::AuthSession, client, constellation, store::{SharedStore, Store}};
+
+pub mod data;
+mod font;
+pub mod png;
+pub mod scene;
+pub mod svg;
+
+/// type representing the query of every export endpoint
+#[derive(serde::Deserialize, Debug)]
+pub struct ExportQuery {
+    /// `short`, `medium` or `long`, like the dashboard. Defaults to `medium`.
+    pub range: Option<String>,
+}
+
+impl ExportQuery {
+    fn range(&self) -> Result<TimeRange, Error> {
+        match &self.range {
+            Some(range) => client::range_from_query(range).ok_or(Error::UnknownRange),
+            None => Ok(TimeRange::MediumTerm),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Not logged in")]
+    Unauthorized,
+
+    #[error("Unknown range")]
+    UnknownRange,
+
+    #[error("{0}")]
+    ServerFn(ServerFnError),
+}
+
+impl From<ServerFnError> for Error {
+    fn from(err: ServerFnError) -> Self {
+        Error::ServerFn(err)
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        match self {
+            Error::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()).into_response(),
+            Error::UnknownRange => (StatusCode::BAD_REQUEST, self.to_string()).into_response(),
+            Error::ServerFn(err) => {
+                tracing::error!("Error exporting: {err}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// The star map as an SVG image, located at `/export/constellation.svg`
+pub async fn constellation_svg(
+    auth_session: AuthSession,
+    State(store): State<SharedStore>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    match constellation_scene(auth_session, &*store, &query).await {
+        Ok(scene) => attachment("image/svg+xml", "constellation.svg", svg::render(&scene)),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// The star map as a PNG image, located at `/export/constellation.png`
+pub async fn constellation_png(
+    auth_session: AuthSession,
+    State(store): State<SharedStore>,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let scene = match constellation_scene(auth_session, &*store, &query).await {
+        Ok(scene) => scene,
+        Err(err) => return err.into_response(),
+    };
+
+    // rasterizing takes a while, so keep it off the async workers
+    match tokio::task::spawn_blocking(move || png::render(&scene)).await {
+        Ok(Ok(png)) => attachment("image/png", "constellation.png", png),
+        Ok(Err(err)) => {
+            tracing::error!("Error rendering PNG: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+        Err(err) => {
+            tracing::error!("Error rendering PNG: {err}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// All of the user's data as JSON, located at `/export/data.json`
+pub async fn data_json(auth_session: AuthSession, State(store): State<SharedStore>) -> Response {
+    match data_export(auth_session, &*store).await {
+        Ok(data) => match serde_json::to_string_pretty(&data) {
+            Ok(json) => attachment("application/json", "starify.json", json),
+            Err(err) => Error::from(ServerFnError::from(err)).into_response(),
+        },
+        Err(err) => err.into_response(),
+    }
+}
+
+/// One table of the user's data as CSV, located at `/export/<table>.csv`
+/// for every [`data::Table`]
+pub async fn data_csv(
+    auth_session: AuthSession,
+    State(store): State<SharedStore>,
+    Path(file): Path<String>,
+) -> Response {
+    let Some(table) = file.strip_suffix(".csv").and_then(data::Table::from_name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match data_export(auth_session, &*store).await {
+        Ok(data) => attachment("text/csv", &format!("starify-{}.csv", table.name()), data.csv(table)),
+        Err(err) => err.into_response(),
+    }
+}
+
+/// The current user's [`data::DataExport`], covering every range.
+async fn data_export(auth_session: AuthSession, store: &dyn Store) -> Result<data::DataExport, Error> {
+    let user = auth_session.user.ok_or(Error::Unauthorized)?;
+
+    let me = client::current_user(store, &user).await?;
+
+    let mut ranges = Vec::new();
+    for range in [TimeRange::ShortTerm, TimeRange::MediumTerm, TimeRange::LongTerm] {
+        let graph = constellation::constellation(store, &user, range).await?;
+        ranges.push((range, graph, client::top_tracks(store, &user, range).await?));
+    }
+
+    let followed = client::followed_artists(store, &user).await?;
+
+    Ok(data::DataExport::build(&me, &ranges, &followed, OffsetDateTime::now_utc().unix_timestamp()))
+}
+
+/// The [`scene::Scene`] of the current user's star map for the requested range.
+async fn constellation_scene(auth_session: AuthSession, store: &dyn Store, query: &ExportQuery) -> Result<scene::Scene, Error> {
+    let range = query.range()?;
+    let user = auth_session.user.ok_or(Error::Unauthorized)?;
+
+    let graph = constellation::constellation(store, &user, range).await?;
+    let me = client::current_user(store, &user).await?;
+
+    // like on the dashboard, artists just go without rings if follows can't be loaded
+    let followed = match client::followed_artists(store, &user).await {
+        Ok(followed) => followed.into_iter().map(|artist| artist.id.to_string()).collect(),
+        Err(err) => {
+            tracing::warn!("Error loading followed artists for export: {err}");
+            HashSet::new()
+        }
+    };
+
+    let caption = scene::Caption {
+        display_name: me.display_name,
+        range: client::range_label(range).to_string(),
+    };
+
+    Ok(scene::build(&graph, &followed, &caption))
+}
+
+/// A response that browsers save as `filename` instead of showing.
+fn attachment(content_type: &'static str, filename: &str, body: impl IntoResponse) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")),
+        ],
+        body,
+    )
+        .into_response()
+}
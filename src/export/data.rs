@@ -0,0 +1,410 @@
+//! The user's data as JSON and CSV, for loading into notebooks and spreadsheets.
+//!
+//! The format is described in the README. Bump [`SCHEMA_VERSION`] whenever a
+//! field is renamed, removed or changes meaning; adding fields is fine.
+
+use std::fmt::Write;
+
+use rspotify::model::{FullArtist, PrivateUser, TimeRange};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{self, TopTrack},
+    constellation::{
+        genres,
+        layout::{self, LayoutOptions},
+        Constellation, EdgeKind,
+    },
+};
+
+/// Version of the [`DataExport`] format.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Everything starify knows about a user.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DataExport {
+    pub schema_version: u32,
+    /// Unix timestamp of the export.
+    pub exported_at: i64,
+    pub profile: Profile,
+    /// Size of the space artist coordinates are in.
+    pub layout: LayoutSize,
+    /// One entry per time range, shortest first.
+    pub ranges: Vec<RangeExport>,
+    pub followed: Vec<FollowedArtist>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    /// Spotify URI, like every ID in the export.
+    pub id: String,
+    pub display_name: Option<String>,
+    pub followers: Option<u32>,
+    pub image: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayoutSize {
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The stats of one time range.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RangeExport {
+    /// `short`, `medium` or `long`.
+    pub range: String,
+    pub label: String,
+    /// The top artists in rank order, which are also the stars of the map.
+    pub artists: Vec<Artist>,
+    pub genres: Vec<Genre>,
+    pub edges: Vec<Link>,
+    pub tracks: Vec<Track>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Artist {
+    /// Starting at 1.
+    pub rank: usize,
+    pub id: String,
+    pub name: String,
+    pub genres: Vec<String>,
+    pub popularity: u32,
+    pub image: Option<String>,
+    /// The genre cluster the artist is drawn in, if any.
+    pub cluster: Option<String>,
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Genre {
+    pub genre: String,
+    /// Share of the range's listening, in `(0, 1]`.
+    pub share: f32,
+    /// Number of top artists with this genre.
+    pub artists: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    /// Starting at 1.
+    pub rank: usize,
+    /// Local files have no ID.
+    pub id: Option<String>,
+    pub name: String,
+    /// Names of the credited artists.
+    pub artists: Vec<String>,
+    pub album: String,
+    pub duration_ms: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FollowedArtist {
+    pub id: String,
+    pub name: String,
+    pub genres: Vec<String>,
+    pub popularity: u32,
+    pub followers: u32,
+    pub image: Option<String>,
+}
+
+/// A connection between two artists of the same range, by artist ID.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Link {
+    pub source: String,
+    pub target: String,
+    /// `related`, `shared_genre` or `shared_track`.
+    pub kind: String,
+    /// Strength in `(0, 1]`.
+    pub weight: f32,
+}
+
+impl DataExport {
+    /// Assemble the export from the user's profile, their constellation and
+    /// top tracks for each range, and the artists they follow.
+    pub fn build(
+        me: &PrivateUser,
+        ranges: &[(TimeRange, Constellation, Vec<TopTrack>)],
+        followed: &[FullArtist],
+        exported_at: i64,
+    ) -> Self {
+        let options = LayoutOptions::default();
+
+        Self {
+            schema_version: SCHEMA_VERSION,
+            exported_at,
+            profile: Profile {
+                id: me.id.to_string(),
+                display_name: me.display_name.clone(),
+                followers: me.followers.as_ref().map(|followers| followers.total),
+                image: me.images.as_ref().and_then(|images| images.first()).map(|image| image.url.clone()),
+            },
+            layout: LayoutSize {
+                width: options.width,
+                height: options.height,
+            },
+            ranges: ranges
+                .iter()
+                .map(|(range, graph, tracks)| RangeExport::build(*range, graph, tracks, &options))
+                .collect(),
+            followed: followed
+                .iter()
+                .map(|artist| FollowedArtist {
+                    id: artist.id.to_string(),
+                    name: artist.name.clone(),
+                    genres: artist.genres.clone(),
+                    popularity: artist.popularity,
+                    followers: artist.followers.total,
+                    image: artist.images.first().map(|image| image.url.clone()),
+                })
+                .collect(),
+        }
+    }
+
+    /// One of the CSV tables of the export.
+    pub fn csv(&self, table: Table) -> String {
+        match table {
+            Table::Profile => {
+                let profile = &self.profile;
+
+                csv(
+                    &["schema_version", "exported_at", "id", "display_name", "followers", "image"],
+                    [vec![
+                        self.schema_version.to_string(),
+                        self.exported_at.to_string(),
+                        profile.id.clone(),
+                        profile.display_name.clone().unwrap_or_default(),
+                        optional(profile.followers),
+                        profile.image.clone().unwrap_or_default(),
+                    ]],
+                )
+            }
+            Table::Artists => csv(
+                &["range", "rank", "id", "name", "genres", "popularity", "image", "cluster", "x", "y"],
+                self.ranges.iter().flat_map(|range| {
+                    range.artists.iter().map(|artist| {
+                        vec![
+                            range.range.clone(),
+                            artist.rank.to_string(),
+                            artist.id.clone(),
+                            artist.name.clone(),
+                            artist.genres.join(";"),
+                            artist.popularity.to_string(),
+                            artist.image.clone().unwrap_or_default(),
+                            artist.cluster.clone().unwrap_or_default(),
+                            format!("{:.2}", artist.x),
+                            format!("{:.2}", artist.y),
+                        ]
+                    })
+                }),
+            ),
+            Table::Genres => csv(
+                &["range", "genre", "share", "artists"],
+                self.ranges.iter().flat_map(|range| {
+                    range.genres.iter().map(|genre| {
+                        vec![
+                            range.range.clone(),
+                            genre.genre.clone(),
+                            format!("{:.4}", genre.share),
+                            genre.artists.to_string(),
+                        ]
+                    })
+                }),
+            ),
+            Table::Edges => csv(
+                &["range", "source", "target", "kind", "weight"],
+                self.ranges.iter().flat_map(|range| {
+                    range.edges.iter().map(|edge| {
+                        vec![
+                            range.range.clone(),
+                            edge.source.clone(),
+                            edge.target.clone(),
+                            edge.kind.clone(),
+                            format!("{:.4}", edge.weight),
+                        ]
+                    })
+                }),
+            ),
+            Table::Tracks => csv(
+                &["range", "rank", "id", "name", "artists", "album", "duration_ms"],
+                self.ranges.iter().flat_map(|range| {
+                    range.tracks.iter().map(|track| {
+                        vec![
+                            range.range.clone(),
+                            track.rank.to_string(),
+                            track.id.clone().unwrap_or_default(),
+                            track.name.clone(),
+                            track.artists.join(";"),
+                            track.album.clone(),
+                            track.duration_ms.to_string(),
+                        ]
+                    })
+                }),
+            ),
+            Table::Followed => csv(
+                &["id", "name", "genres", "popularity", "followers", "image"],
+                self.followed.iter().map(|artist| {
+                    vec![
+                        artist.id.clone(),
+                        artist.name.clone(),
+                        artist.genres.join(";"),
+                        artist.popularity.to_string(),
+                        artist.followers.to_string(),
+                        artist.image.clone().unwrap_or_default(),
+                    ]
+                }),
+            ),
+        }
+    }
+}
+
+impl RangeExport {
+    fn build(range: TimeRange, graph: &Constellation, tracks: &[TopTrack], options: &LayoutOptions) -> Self {
+        let positions = layout::layout(graph, options);
+
+        let mut clusters = vec![None; graph.nodes.len()];
+        for cluster in genres::clusters(&graph.nodes) {
+            for &member in &cluster.members {
+                clusters[member] = Some(cluster.name.clone());
+            }
+        }
+
+        Self {
+            range: client::range_to_query(range).to_string(),
+            label: client::range_label(range).to_string(),
+            artists: graph
+                .nodes
+                .iter()
+                .zip(positions)
+                .zip(clusters)
+                .map(|((node, position), cluster)| Artist {
+                    rank: node.rank + 1,
+                    id: node.id.clone(),
+                    name: node.name.clone(),
+                    genres: node.genres.clone(),
+                    popularity: node.popularity,
+                    image: node.image.clone(),
+                    cluster,
+                    x: position.x,
+                    y: position.y,
+                })
+                .collect(),
+            genres: genres::breakdown(&graph.nodes)
+                .into_iter()
+                .map(|share| Genre {
+                    genre: share.genre,
+                    share: share.share,
+                    artists: share.artists,
+                })
+                .collect(),
+            edges: graph
+                .edges
+                .iter()
+                .map(|edge| Link {
+                    source: graph.nodes[edge.source].id.clone(),
+                    target: graph.nodes[edge.target].id.clone(),
+                    kind: edge_kind(edge.kind).to_string(),
+                    weight: edge.weight,
+                })
+                .collect(),
+            tracks: tracks
+                .iter()
+                .enumerate()
+                .map(|(index, track)| Track {
+                    rank: index + 1,
+                    id: track.id.clone(),
+                    name: track.name.clone(),
+                    artists: track.artists.iter().map(|artist| artist.name.clone()).collect(),
+                    album: track.album.clone(),
+                    duration_ms: track.duration_ms,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The CSV files of an export, each covering every range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Table {
+    Profile,
+    Artists,
+    Genres,
+    Edges,
+    Tracks,
+    Followed,
+}
+
+impl Table {
+    pub const ALL: [Table; 6] = [
+        Table::Profile,
+        Table::Artists,
+        Table::Genres,
+        Table::Edges,
+        Table::Tracks,
+        Table::Followed,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Table::Profile => "profile",
+            Table::Artists => "artists",
+            Table::Genres => "genres",
+            Table::Edges => "edges",
+            Table::Tracks => "tracks",
+            Table::Followed => "followed",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|table| table.name() == name)
+    }
+}
+
+fn edge_kind(kind: EdgeKind) -> &'static str {
+    match kind {
+        EdgeKind::Related => "related",
+        EdgeKind::SharedGenre => "shared_genre",
+        EdgeKind::SharedTrack => "shared_track",
+    }
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+/// Write `rows` under `header` as RFC 4180 CSV.
+fn csv(header: &[&str], rows: impl IntoIterator<Item = Vec<String>>) -> String {
+    let mut csv = String::new();
+
+    let mut write_row = |fields: &mut dyn Iterator<Item = &str>| {
+        let row: Vec<String> = fields.map(escape).collect();
+        // writing to a string can't fail
+        let _ = write!(csv, "{}\r\n", row.join(","));
+    };
+
+    write_row(&mut header.iter().copied());
+
+    for row in rows {
+        write_row(&mut row.iter().map(String::as_str));
+    }
+
+    csv
+}
+
+/// Quote `field` if it contains a separator, quote or line break.
+///
+/// Fields spreadsheets would read as a formula, like an artist named
+/// `=HYPERLINK(…)`, are prefixed with `'` so they're shown as text.
+fn escape(field: &str) -> String {
+    let field = match field.starts_with(['=', '+', '-', '@']) {
+        true => format!("'{field}"),
+        false => field.to_string(),
+    };
+
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
@@ -0,0 +1,33 @@
+//! DejaVu Sans, bundled so exported images look the same whatever fonts the
+//! server has, and cover names like Björk or Sigur Rós. See `fonts/LICENSE`.
+
+use std::sync::OnceLock;
+
+use ttf_parser::Face;
+
+/// The family name to ask for in SVGs.
+pub const FAMILY: &str = "DejaVu Sans";
+
+pub const DATA: &[u8] = include_bytes!("fonts/DejaVuSans.ttf");
+
+fn face() -> &'static Face<'static> {
+    static FACE: OnceLock<Face<'static>> = OnceLock::new();
+
+    FACE.get_or_init(|| Face::parse(DATA, 0).expect("the bundled font parses"))
+}
+
+/// Width of `text` at `size`, the sum of its glyphs' advances.
+/// Characters the font lacks count as its missing glyph box.
+pub fn text_width(text: &str, size: f64) -> f64 {
+    let face = face();
+
+    let advances: u32 = text
+        .chars()
+        .map(|c| {
+            let glyph = face.glyph_index(c).unwrap_or_default();
+            face.glyph_hor_advance(glyph).unwrap_or_default() as u32
+        })
+        .sum();
+
+    advances as f64 * size / face.units_per_em() as f64
+}
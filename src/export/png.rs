@@ -0,0 +1,51 @@
+//! Rasterizes the [`svg`] rendering of a [`Scene`], so both exports look the
+//! same. Text is drawn in the bundled [`font`], never in system fonts.
+
+use std::sync::{Arc, OnceLock};
+
+use resvg::{tiny_skia::Pixmap, usvg};
+use thiserror::Error;
+
+use super::{
+    font,
+    scene::{Scene, HEIGHT, WIDTH},
+    svg,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Invalid SVG: {0}")]
+    Svg(#[from] usvg::Error),
+
+    #[error("Couldn't encode PNG: {0}")]
+    Encode(String),
+}
+
+/// A font database with only the bundled font, loaded once.
+fn fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+
+    FONTS
+        .get_or_init(|| {
+            let mut fonts = usvg::fontdb::Database::new();
+            fonts.load_font_data(font::DATA.to_vec());
+            Arc::new(fonts)
+        })
+        .clone()
+}
+
+/// Render `scene` to an encoded PNG.
+pub fn render(scene: &Scene) -> Result<Vec<u8>, Error> {
+    let options = usvg::Options {
+        font_family: font::FAMILY.to_string(),
+        fontdb: fonts(),
+        ..Default::default()
+    };
+
+    let tree = usvg::Tree::from_str(&svg::render(scene), &options)?;
+
+    let mut pixmap = Pixmap::new(WIDTH as u32, HEIGHT as u32).expect("the image isn't empty");
+    resvg::render(&tree, Default::default(), &mut pixmap.as_mut());
+
+    pixmap.encode_png().map_err(|err| Error::Encode(err.to_string()))
+}
@@ -0,0 +1,231 @@
+//! The exported image as a list of shapes, drawn the same way by both
+//! [`super::svg`] and [`super::png`].
+
+use std::collections::HashSet;
+
+use crate::constellation::{
+    genres,
+    layout::{self, LayoutOptions, Point},
+    Constellation,
+};
+
+use super::font;
+
+/// Size of the exported image, the usual aspect ratio for link previews.
+pub const WIDTH: f64 = 1200.0;
+pub const HEIGHT: f64 = 630.0;
+
+const MARGIN: f64 = 30.0;
+const TEXT_LEFT: f64 = 60.0;
+/// Right edge of the text column, left of the map.
+const TEXT_RIGHT: f64 = WIDTH - HEIGHT - MARGIN;
+
+/// How many top artists are listed next to the map.
+const LISTED_ARTISTS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    /// Opacity in `[0, 1]`.
+    pub a: f64,
+}
+
+impl Color {
+    pub const BACKGROUND: Color = Color::rgb(15, 23, 42);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b, a: 1.0 }
+    }
+
+    pub fn with_alpha(self, a: f64) -> Self {
+        Self { a: a.clamp(0.0, 1.0), ..self }
+    }
+
+    /// As `#rrggbb`, without the opacity.
+    pub fn hex(&self) -> String {
+        format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Line {
+        from: Point,
+        to: Point,
+        width: f64,
+        color: Color,
+        /// Dash and gap length, or `None` for a solid line.
+        dash: Option<(f64, f64)>,
+    },
+    Circle {
+        center: Point,
+        radius: f64,
+        fill: Option<Color>,
+        stroke: Option<Stroke>,
+    },
+    /// A line of text starting at `position`, which is the top left of the first glyph.
+    Text {
+        position: Point,
+        size: f64,
+        text: String,
+        color: Color,
+    },
+}
+
+/// The outline of a [`Shape::Circle`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stroke {
+    pub color: Color,
+    pub width: f64,
+    /// Dash and gap length, or `None` for a solid outline.
+    pub dash: Option<(f64, f64)>,
+}
+
+/// Everything to draw, back to front, on a [`WIDTH`] by [`HEIGHT`] canvas filled with `background`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scene {
+    pub background: Color,
+    pub shapes: Vec<Shape>,
+}
+
+/// What's written next to the map.
+pub struct Caption {
+    pub display_name: Option<String>,
+    pub range: String,
+}
+
+/// Lay out `constellation` exactly like the dashboard does and place it on
+/// the right of the image, with `caption` and the top artists on the left.
+/// Artists in `followed` (by ID) get a ring, as on the dashboard.
+pub fn build(constellation: &Constellation, followed: &HashSet<String>, caption: &Caption) -> Scene {
+    let options = LayoutOptions::default();
+    let positions = layout::layout(constellation, &options);
+    let count = constellation.nodes.len();
+
+    // the map is a square filling the height of the image
+    let size = HEIGHT - 2.0 * MARGIN;
+    let scale = size / options.width.max(options.height);
+    let origin = Point { x: WIDTH - MARGIN - size, y: MARGIN };
+    let place = |point: Point| Point {
+        x: origin.x + point.x * scale,
+        y: origin.y + point.y * scale,
+    };
+
+    let mut shapes = Vec::new();
+
+    for cluster in genres::clusters(&constellation.nodes) {
+        let (center, radius) = layout::cluster_region(&cluster, &constellation.nodes, &positions);
+        let center = place(center);
+        let radius = radius * scale;
+
+        shapes.push(Shape::Circle {
+            center,
+            radius,
+            fill: Some(Color::WHITE.with_alpha(0.02 + 0.06 * cluster.weight as f64)),
+            stroke: Some(Stroke {
+                color: Color::WHITE.with_alpha(0.15),
+                width: 1.0,
+                dash: Some((2.0 * scale, 8.0 * scale)),
+            }),
+        });
+
+        let label_size = 10.0;
+        shapes.push(Shape::Text {
+            position: Point {
+                x: center.x - font::text_width(&cluster.name, label_size) / 2.0,
+                y: (center.y - radius - 6.0 - label_size).max(origin.y),
+            },
+            size: label_size,
+            text: cluster.name,
+            color: Color::WHITE.with_alpha(0.6),
+        });
+    }
+
+    for edge in &constellation.edges {
+        let weight = edge.weight as f64;
+
+        shapes.push(Shape::Line {
+            from: place(positions[edge.source]),
+            to: place(positions[edge.target]),
+            width: (1.0 + 2.0 * weight) * scale,
+            color: Color::WHITE.with_alpha(0.1 + 0.5 * weight),
+            dash: layout::edge_dash(edge.kind).map(|(dash, gap)| (dash * scale, gap * scale)),
+        });
+    }
+
+    for (node, position) in constellation.nodes.iter().zip(&positions) {
+        if followed.contains(&node.id) {
+            shapes.push(Shape::Circle {
+                center: place(*position),
+                radius: (layout::star_radius(node) + 5.0) * scale,
+                fill: None,
+                stroke: Some(Stroke {
+                    color: Color::WHITE.with_alpha(0.6),
+                    width: scale,
+                    dash: None,
+                }),
+            });
+        }
+
+        shapes.push(Shape::Circle {
+            center: place(*position),
+            radius: layout::star_radius(node) * scale,
+            fill: Some(Color::WHITE.with_alpha(layout::star_brightness(node, count))),
+            stroke: None,
+        });
+    }
+
+    let mut y = 90.0;
+    let mut text = |text: String, size: f64, alpha: f64, gap: f64| {
+        shapes.push(Shape::Text {
+            position: Point { x: TEXT_LEFT, y },
+            text: fit(&text, size, TEXT_RIGHT - TEXT_LEFT),
+            size,
+            color: Color::WHITE.with_alpha(alpha),
+        });
+
+        y += size + gap;
+    };
+
+    text("Star Map".to_string(), 56.0, 1.0, 24.0);
+
+    if let Some(name) = &caption.display_name {
+        text(name.clone(), 32.0, 0.9, 12.0);
+    }
+
+    text(caption.range.clone(), 24.0, 0.7, 40.0);
+
+    for node in constellation.nodes.iter().take(LISTED_ARTISTS) {
+        text(format!("{}. {}", node.rank + 1, node.name), 20.0, 0.8, 14.0);
+    }
+
+    shapes.push(Shape::Text {
+        position: Point { x: TEXT_LEFT, y: HEIGHT - MARGIN - 40.0 },
+        size: 16.0,
+        text: "starify".to_string(),
+        color: Color::WHITE.with_alpha(0.5),
+    });
+
+    Scene {
+        background: Color::BACKGROUND,
+        shapes,
+    }
+}
+
+/// Cut `text` short with `...` so it's at most `max_width` wide at `size`.
+fn fit(text: &str, size: f64, max_width: f64) -> String {
+    if font::text_width(text, size) <= max_width {
+        return text.to_string();
+    }
+
+    let mut fitted = text.to_string();
+
+    while !fitted.is_empty() && font::text_width(&format!("{fitted}..."), size) > max_width {
+        fitted.pop();
+    }
+
+    fitted + "..."
+}
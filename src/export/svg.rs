@@ -0,0 +1,88 @@
+use std::fmt::Write;
+
+use super::{
+    font,
+    scene::{Scene, Shape, HEIGHT, WIDTH},
+};
+
+/// Render `scene` as a standalone SVG document.
+pub fn render(scene: &Scene) -> String {
+    let mut svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}">"#
+    );
+
+    let _ = write!(
+        svg,
+        r#"<rect width="100%" height="100%" fill="{}"/>"#,
+        scene.background.hex()
+    );
+
+    for shape in &scene.shapes {
+        // writing to a string can't fail
+        let _ = match shape {
+            Shape::Line { from, to, width, color, dash } => write!(
+                svg,
+                r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="{}" stroke-opacity="{:.3}" stroke-width="{:.2}"{}/>"#,
+                from.x,
+                from.y,
+                to.x,
+                to.y,
+                color.hex(),
+                color.a,
+                width,
+                dash_attribute(*dash),
+            ),
+            Shape::Circle { center, radius, fill, stroke } => {
+                let fill = match fill {
+                    Some(fill) => format!(r#"fill="{}" fill-opacity="{:.3}""#, fill.hex(), fill.a),
+                    None => r#"fill="none""#.to_string(),
+                };
+
+                let stroke = match stroke {
+                    Some(stroke) => format!(
+                        r#" stroke="{}" stroke-opacity="{:.3}" stroke-width="{:.2}"{}"#,
+                        stroke.color.hex(),
+                        stroke.color.a,
+                        stroke.width,
+                        dash_attribute(stroke.dash),
+                    ),
+                    None => String::new(),
+                };
+
+                write!(
+                    svg,
+                    r#"<circle cx="{:.2}" cy="{:.2}" r="{:.2}" {fill}{stroke}/>"#,
+                    center.x, center.y, radius,
+                )
+            }
+            // the PNG is drawn in the bundled font, viewers without it get something close
+            Shape::Text { position, size, text, color } => write!(
+                svg,
+                r#"<text x="{:.2}" y="{:.2}" font-family="'{}', sans-serif" font-size="{size}" fill="{}" fill-opacity="{:.3}" dominant-baseline="text-before-edge">{}</text>"#,
+                position.x,
+                position.y,
+                font::FAMILY,
+                color.hex(),
+                color.a,
+                escape(text),
+            ),
+        };
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+fn dash_attribute(dash: Option<(f64, f64)>) -> String {
+    match dash {
+        Some((dash, gap)) => format!(r#" stroke-dasharray="{dash:.2} {gap:.2}""#),
+        None => String::new(),
+    }
+}
+
+/// Escape `text` for use in XML character data.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,320 @@
+//! Dated snapshots of each user's top artists, so changes in taste can be shown over time.
+
+use leptos::*;
+use rspotify::model::{FullArtist, TimeRange};
+use serde::{Deserialize, Serialize};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use axum_login::AuthnBackend;
+        use time::{Duration, OffsetDateTime};
+
+        use crate::{
+            auth::{tokens, AuthSession, Backend, User},
+            client,
+            store::{self, schema, SharedStore, Store, Stored, DEFAULT_TREE},
+        };
+
+        /// Name of the tree snapshots are stored in, under `{user_id}/{range}_{date}`
+        /// so a user's snapshots of a range sort by date.
+        pub const HISTORY_TREE: &str = "history";
+
+        const RANGES: [TimeRange; 3] = [TimeRange::ShortTerm, TimeRange::MediumTerm, TimeRange::LongTerm];
+
+        fn history_prefix(user_id: &str, range: TimeRange) -> String {
+            store::user_key(user_id, &format!("{}_", client::range_to_query(range)))
+        }
+
+        /// Today in UTC, as `YYYY-MM-DD`.
+        fn today() -> String {
+            OffsetDateTime::now_utc().date().to_string()
+        }
+
+        /// Store `artists` as today's snapshot of `range` for `user_id`, unless
+        /// there already is one. Returns whether a snapshot was stored.
+        pub fn record(store: &dyn Store, user_id: &str, range: TimeRange, artists: &[FullArtist]) -> Result<bool, store::Error> {
+            let now = OffsetDateTime::now_utc();
+
+            put_snapshot(store, user_id, range, &HistorySnapshot {
+                date: now.date().to_string(),
+                taken_at: now.unix_timestamp(),
+                artists: artists.iter().map(HistoryArtist::from).collect(),
+            })
+        }
+
+        /// Store `snapshot` for `user_id` unless there's already one for its date.
+        pub fn put_snapshot(store: &dyn Store, user_id: &str, range: TimeRange, snapshot: &HistorySnapshot) -> Result<bool, store::Error> {
+            let key = format!("{}{}", history_prefix(user_id, range), snapshot.date);
+            let value = schema::encode(snapshot);
+
+            // only the first snapshot of a day is kept, even with concurrent requests
+            store.compare_and_swap(HISTORY_TREE, &key, None, Some(value))
+        }
+
+        impl Stored for HistorySnapshot {
+            const TAG: &'static str = "history_snapshot";
+        }
+
+        /// Every readable snapshot of `range` for `user_id`, oldest first.
+        pub fn snapshots(store: &dyn Store, user_id: &str, range: TimeRange) -> Result<Vec<HistorySnapshot>, store::Error> {
+            Ok(store
+                .scan_prefix(HISTORY_TREE, &history_prefix(user_id, range))?
+                .into_iter()
+                .filter_map(|(key, out)| schema::decode_or_log(HISTORY_TREE, &key, &out))
+                .collect())
+        }
+
+        /// Remove every snapshot of `user_id`.
+        pub fn delete_user_history(store: &dyn Store, user_id: &str) -> Result<(), store::Error> {
+            for (key, _) in store.scan_prefix(HISTORY_TREE, &store::user_prefix(user_id))? {
+                store.delete(HISTORY_TREE, &key)?;
+            }
+
+            Ok(())
+        }
+
+        /// Every user with a stored token and a snapshot missing today, for at least one range.
+        ///
+        /// Users whose refresh token was revoked have no token anymore, so
+        /// they're skipped until they log in again.
+        fn users_missing_today(store: &dyn Store) -> Result<Vec<String>, store::Error> {
+            let today = today();
+
+            Ok(store
+                .scan_prefix(DEFAULT_TREE, "")?
+                .into_iter()
+                // tokens are stored under the bare user ID, everything else under a user key
+                .filter(|(key, out)| !key.contains(store::USER_KEY_SEPARATOR) && tokens::is_token_entry(out))
+                .map(|(user_id, _)| user_id)
+                .filter(|user_id| {
+                    RANGES.iter().any(|&range| {
+                        let key = format!("{}{today}", history_prefix(user_id, range));
+                        store.get(HISTORY_TREE, &key).is_ok_and(|out| out.is_none())
+                    })
+                })
+                .collect())
+        }
+
+        /// Take today's snapshots for every known user who doesn't have them yet,
+        /// returning how many users got at least one.
+        pub async fn take_snapshots(backend: &Backend) -> Result<usize, store::Error> {
+            let mut taken = 0;
+
+            for user_id in users_missing_today(backend.store())? {
+                if take_user_snapshots(backend, &user_id).await? {
+                    taken += 1;
+                }
+            }
+
+            Ok(taken)
+        }
+
+        /// Fetch and store the snapshots `user_id` is missing today, returning
+        /// whether any were stored.
+        ///
+        /// Users whose refresh token Spotify no longer accepts are skipped;
+        /// [`Backend`] forgets their token, so they're only tried again once they log in.
+        pub async fn take_user_snapshots(backend: &Backend, user_id: &str) -> Result<bool, store::Error> {
+            let store = backend.store();
+
+            let user = match backend.get_user(&user_id.to_string()).await {
+                Ok(Some(user)) => user,
+                Ok(None) => return Ok(false),
+                Err(err) => {
+                    tracing::warn!("Error loading {user_id} for history: {err}");
+                    return Ok(false);
+                }
+            };
+
+            let today = today();
+            let mut stored = false;
+
+            for range in RANGES {
+                let key = format!("{}{today}", history_prefix(user_id, range));
+                if store.get(HISTORY_TREE, &key)?.is_some() {
+                    continue;
+                }
+
+                // not from the cache, which may be days old
+                match client::fetch_top_artists(&user, range).await {
+                    Ok(artists) => stored |= record(store, user_id, range, &artists)?,
+                    Err(err) => tracing::warn!("Error fetching top artists of {user_id} for history: {err}"),
+                }
+            }
+
+            Ok(stored)
+        }
+
+        /// Take missing snapshots every `period`, forever.
+        pub async fn continuously_take_snapshots(backend: Backend, period: std::time::Duration) {
+            let mut interval = tokio::time::interval(period);
+
+            loop {
+                interval.tick().await;
+
+                match take_snapshots(&backend).await {
+                    Ok(0) => (),
+                    Ok(taken) => tracing::debug!("Took history snapshots for {taken} users"),
+                    Err(err) => tracing::error!("Error taking history snapshots: {err}"),
+                }
+            }
+        }
+
+        /// How the top artists of `user` for `range` changed over the last `days` days.
+        pub async fn rank_movement(store: &dyn Store, user: &User, range: TimeRange, days: u32) -> Result<RankMovement, ServerFnError> {
+            // takes today's snapshot, unless the cached artists are still fresh
+            client::top_artists(store, user, range).await?;
+
+            let mut snapshots = snapshots(store, &user.user_id, range)
+                .map_err(|err| ServerFnError::ServerError(format!("Error reading history: {err}")))?;
+
+            let Some(current) = snapshots.pop() else {
+                return Err(ServerFnError::ServerError("No history to compare".to_string()));
+            };
+
+            let since = OffsetDateTime::now_utc()
+                .date()
+                .checked_sub(Duration::days(days.into()))
+                .ok_or_else(|| ServerFnError::ServerError(format!("Can't look back {days} days")))?
+                .to_string();
+
+            // the newest snapshot from at least `days` ago, or else the oldest there is
+            let previous = snapshots
+                .iter()
+                .rev()
+                .find(|snapshot| snapshot.date <= since)
+                .or(snapshots.first());
+
+            Ok(RankMovement::between(previous, &current))
+        }
+    }
+}
+
+/// The parts of an artist a [`HistorySnapshot`] keeps.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryArtist {
+    pub id: String,
+    pub name: String,
+    pub image: Option<String>,
+}
+
+impl From<&FullArtist> for HistoryArtist {
+    fn from(artist: &FullArtist) -> Self {
+        Self {
+            id: artist.id.to_string(),
+            name: artist.name.clone(),
+            image: artist.images.first().map(|image| image.url.clone()),
+        }
+    }
+}
+
+/// A user's top artists of one range on one day.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistorySnapshot {
+    /// The UTC day the snapshot is of, as `YYYY-MM-DD`.
+    pub date: String,
+    /// When the snapshot was taken, as a unix timestamp.
+    pub taken_at: i64,
+    /// In rank order.
+    pub artists: Vec<HistoryArtist>,
+}
+
+/// Where an artist placed in two snapshots, with ranks starting at 1.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RankChange {
+    pub artist: HistoryArtist,
+    /// `None` if the artist dropped out.
+    pub rank: Option<usize>,
+    /// `None` if the artist is new.
+    pub previous_rank: Option<usize>,
+}
+
+/// Which way a [`RankChange`] went.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Movement {
+    Rising(usize),
+    Falling(usize),
+    Unchanged,
+    New,
+    Dropped,
+}
+
+impl RankChange {
+    pub fn movement(&self) -> Movement {
+        match (self.previous_rank, self.rank) {
+            (None, _) => Movement::New,
+            (_, None) => Movement::Dropped,
+            (Some(previous), Some(rank)) if rank < previous => Movement::Rising(previous - rank),
+            (Some(previous), Some(rank)) if rank > previous => Movement::Falling(rank - previous),
+            _ => Movement::Unchanged,
+        }
+    }
+}
+
+/// How a user's top artists changed between two snapshots.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RankMovement {
+    /// Date of the earlier snapshot, or `None` if there's only one so far.
+    pub since: Option<String>,
+    /// Date of the latest snapshot.
+    pub until: String,
+    /// The current artists in rank order, followed by those that dropped out.
+    pub changes: Vec<RankChange>,
+}
+
+impl RankMovement {
+    /// Compare `current` with `previous`. Without a previous snapshot there are no changes.
+    pub fn between(previous: Option<&HistorySnapshot>, current: &HistorySnapshot) -> Self {
+        let Some(previous) = previous else {
+            return Self {
+                since: None,
+                until: current.date.clone(),
+                changes: Vec::new(),
+            };
+        };
+
+        let rank_in = |snapshot: &HistorySnapshot, id: &str| {
+            snapshot.artists.iter().position(|artist| artist.id == id).map(|index| index + 1)
+        };
+
+        let current_changes = current.artists.iter().enumerate().map(|(index, artist)| RankChange {
+            artist: artist.clone(),
+            rank: Some(index + 1),
+            previous_rank: rank_in(previous, &artist.id),
+        });
+
+        let dropped = previous
+            .artists
+            .iter()
+            .enumerate()
+            .filter(|(_, artist)| rank_in(current, &artist.id).is_none())
+            .map(|(index, artist)| RankChange {
+                artist: artist.clone(),
+                rank: None,
+                previous_rank: Some(index + 1),
+            });
+
+        Self {
+            since: Some(previous.date.clone()),
+            until: current.date.clone(),
+            changes: current_changes.chain(dropped).collect(),
+        }
+    }
+}
+
+/// How the current user's top artists for `range` changed over the last `days` days.
+#[server]
+pub async fn get_rank_movement(range: TimeRange, days: u32) -> Result<Option<RankMovement>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        let Some(user) = use_context::<AuthSession>()
+            .expect("no auth session provided")
+            .user else {
+                return Ok(None);
+            };
+
+        let store = use_context::<SharedStore>().expect("no store provided");
+
+        rank_movement(&*store, &user, range, days).await.map(Some)
+    }
+}
@@ -1,11 +1,28 @@
 pub mod app;
 pub mod errors;
 pub mod client;
+pub mod constellation;
+pub mod history;
+pub mod share;
 
 #[cfg(feature = "ssr")]
 pub mod auth;
+#[cfg(feature = "ssr")]
+pub mod config;
+#[cfg(feature = "ssr")]
+pub mod export;
+#[cfg(feature = "ssr")]
+pub mod origin;
+#[cfg(feature = "ssr")]
+pub mod server;
+#[cfg(feature = "ssr")]
+pub mod session;
+#[cfg(feature = "ssr")]
+pub mod store;
 
 pub const CALLBACK_ENDPOINT: &str = "/authorize";
+pub const LOGOUT_ENDPOINT: &str = "/logout";
+pub const EXPORT_ENDPOINT: &str = "/export";
 pub const LOGIN_STATE_KEY: &str = "login_state";
 pub const SPOTIFY_SCOPES: [&str; 2] = ["user-top-read", "user-follow-read"];
 
@@ -1,38 +1,23 @@
-use std::{collections::HashSet, env, net::SocketAddr, str::FromStr};
+use std::{net::SocketAddr, sync::Arc};
 
 use axum::{
     body::Body as AxumBody,
-    error_handling::HandleErrorLayer,
-    extract::{Path, RawQuery, State, FromRef},
-    http::StatusCode,
-    http::{header, HeaderMap, Request, Uri},
+    extract::State,
+    http::{header, Request, Uri},
     response::IntoResponse,
-    routing::get,
-    BoxError, Router,
-};
-use axum_login::{
-    tower_sessions::{cookie::SameSite, Expiry, MemoryStore, SessionManagerLayer},
-    AuthManagerLayerBuilder,
 };
 use color_eyre::eyre;
-use leptos_axum::{generate_route_list, LeptosRoutes};
-use rspotify::{AuthCodeSpotify, Config, Credentials, OAuth};
-use time::Duration;
-use tower::ServiceBuilder;
 
 use starify::{
     app::App,
-    auth::{self, Backend, AuthSession},
-    CALLBACK_ENDPOINT, SPOTIFY_SCOPES,
+    client,
+    config,
+    history,
+    server::{self, AppState},
+    session::{self, Sessions},
+    store::{migrations, SharedStore, SledStore, Store},
 };
 
-#[derive(FromRef, Debug, Clone)]
-pub struct AppState {
-    pub leptos_options: leptos::LeptosOptions,
-    pub routes: Vec<leptos_router::RouteListing>,
-    pub spotify_credentials: rspotify::Credentials,
-}
-
 #[tokio::main]
 async fn main() -> eyre::Result<()> {
     tracing_subscriber::fmt::init();
@@ -41,73 +26,76 @@ async fn main() -> eyre::Result<()> {
     // get leptos configuration from environment variables injected by cargo-leptos
     let mut conf = leptos::get_configuration(None).await.unwrap();
 
-    if let Ok(Ok(socket)) = env::var("STARIFY_SOCKET").map(|var| SocketAddr::from_str(&var)) {
-        conf.leptos_options.site_addr = socket;
+    // validate everything up front, so a bad setting can't fail deep inside a request
+    let config = config::init(config::Config::load(conf.leptos_options.site_addr)?);
+
+    conf.leptos_options.site_addr = config.bind_address;
+
+    if config.token_keys.is_empty() {
+        tracing::warn!("No token_keys are configured, Spotify tokens will be stored unencrypted");
     }
 
     let addr = conf.leptos_options.site_addr;
-    let routes = generate_route_list(App);
-
-    let app_state = AppState {
-        leptos_options: conf.leptos_options.clone(),
-        routes: routes.clone(),
-        spotify_credentials: Credentials {
-            id: match env::var("SPOTIFY_CLIENT_ID") {
-                Ok(var) => var,
-                Err(err) => return Err(eyre::anyhow!(err))
-            },
-            secret: match env::var("SPOTIFY_CLIENT_SECRET") {
-                Ok(var) => Some(var),
-                Err(err) => return Err(eyre::anyhow!(err))
-            },
-        },
-    };
-
-    let backend = Backend::new(AuthCodeSpotify::with_config(
-        app_state.spotify_credentials.clone(),
-        OAuth {
-            redirect_uri: format!(
-                "http://{}{CALLBACK_ENDPOINT}",
-                &app_state.leptos_options.site_addr
-            ),
-            scopes: HashSet::from(SPOTIFY_SCOPES.map(|s| s.into())),
-            ..Default::default()
-        },
-        Config {
-            token_cached: false,
-            ..Default::default()
-        },
+
+    let store: SharedStore = Arc::new(SledStore::open(&config.database_path)?);
+
+    let mut args = std::env::args().skip(1);
+
+    match args.next().as_deref() {
+        None => (),
+        Some("migrate") => return migrate(&*store, args.any(|arg| arg == "--dry-run")),
+        Some(command) => {
+            eyre::bail!("Unknown command `{command}`, expected none or `migrate [--dry-run]`");
+        }
+    }
+
+    // bring stored data up to date before anything reads it
+    for report in migrations::migrate(&*store, false)? {
+        tracing::info!("Migrated storage to schema version {report}");
+    }
+
+    tokio::task::spawn(session::continuously_delete_expired(
+        Sessions::new(store.clone()),
+        std::time::Duration::from_secs(60 * 60),
     ));
 
-    // TODO: write sled db SessionStore
-    let session_layer = SessionManagerLayer::new(MemoryStore::default())
-        .with_same_site(SameSite::Lax)
-        .with_expiry(Expiry::OnInactivity(Duration::days(1)));
-
-    let router = Router::new()
-        .route(CALLBACK_ENDPOINT, get(auth::authorize))
-        .route(
-            "/api/*fn_name",
-            get(server_fn_handler).post(server_fn_handler),
-        )
-        .leptos_routes_with_handler(routes, get(leptos_routes_handler))
-        .fallback(static_handler)
-        .with_state(app_state)
-        .layer(ServiceBuilder::new()
-            .layer(HandleErrorLayer::new(|_: BoxError| async {
-                StatusCode::BAD_REQUEST
-            }))
-            .layer(AuthManagerLayerBuilder::new(backend, session_layer).build())
-        );
+    tokio::task::spawn(client::continuously_prune_cache(store.clone(), std::time::Duration::from_secs(15 * 60)));
+
+    tokio::task::spawn(history::continuously_take_snapshots(
+        server::backend(server::spotify_client(config), config, store.clone()),
+        std::time::Duration::from_secs(60 * 60),
+    ));
+
+    let router = server::router(config, conf.leptos_options, store, static_handler);
 
     tracing::info!("Listening on http://{addr}/");
     axum::Server::bind(&addr)
-        .serve(router.into_make_service())
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
 }
 
+/// `starify migrate [--dry-run]`: run pending migrations, or only show what they would change.
+fn migrate(store: &dyn Store, dry_run: bool) -> eyre::Result<()> {
+    let current = migrations::schema_version(store)?;
+    let reports = migrations::migrate(store, dry_run)?;
+
+    println!("Schema version {current}, this release uses {}", migrations::SCHEMA_VERSION);
+
+    for report in &reports {
+        println!("{report}");
+    }
+
+    match (reports.is_empty(), dry_run) {
+        (true, _) => println!("Nothing to migrate"),
+        (false, true) => println!("Dry run, nothing was changed"),
+        (false, false) => println!("Done"),
+    }
+
+    Ok(())
+}
+
 #[derive(rust_embed::RustEmbed)]
 #[folder = "$LEPTOS_SITE_ROOT/"]
 struct Asset;
@@ -133,46 +121,3 @@ async fn static_handler(
         .into_response(),
     }
 }
-
-/// Handle leptos routes and inject state for payload
-async fn leptos_routes_handler(
-    session: AuthSession,
-    State(app_state): State<AppState>,
-    req: Request<AxumBody>,
-) -> impl IntoResponse {
-    let handler = leptos_axum::render_route_with_context(
-        app_state.leptos_options.clone(),
-        app_state.routes.clone(),
-        move || provide_state_context(&session, &app_state),
-        App,
-    );
-
-    handler(req).await
-}
-
-/// Handle leptos server functions and inject state for pageload
-async fn server_fn_handler(
-    session: AuthSession,
-    State(app_state): State<AppState>,
-    path: Path<String>,
-    headers: HeaderMap,
-    raw_query: RawQuery,
-    req: Request<AxumBody>,
-) -> impl IntoResponse {
-    leptos_axum::handle_server_fns_with_context(
-        path,
-        headers,
-        raw_query,
-        move || provide_state_context(&session, &app_state),
-        req,
-    )
-    .await
-}
-
-/// Provide leptos context for each [`AppState`] field.
-fn provide_state_context(session: &AuthSession, app_state: &AppState) {
-    leptos::provide_context(app_state.spotify_credentials.clone());
-    leptos::provide_context(app_state.leptos_options.clone());
-    leptos::provide_context(session.clone());
-}
-
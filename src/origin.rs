@@ -0,0 +1,82 @@
+use std::net::{IpAddr, SocketAddr};
+
+use async_trait::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use http::{request::Parts, HeaderMap};
+
+use crate::{config::Config, CALLBACK_ENDPOINT};
+
+/// The scheme and host a request was made to from the user's point of view,
+/// e.g. `https://starify.example.com`.
+///
+/// This is the configured public URL, unless the request came through a
+/// trusted proxy that says otherwise with `X-Forwarded-Proto` or `X-Forwarded-Host`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Origin(pub String);
+
+impl Origin {
+    pub fn resolve(config: &Config, peer: Option<IpAddr>, headers: &HeaderMap) -> Self {
+        let trusted = peer.is_some_and(|peer| config.trusted_proxies.contains(&peer));
+
+        if !trusted {
+            return Self(config.public_url.clone());
+        }
+
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                // proxies may append to the header, the first entry is the client's
+                .and_then(|value| value.split(',').next())
+                .map(str::trim)
+        };
+
+        let (default_scheme, default_host) = config
+            .public_url
+            .split_once("://")
+            .expect("public_url has a scheme");
+
+        let scheme = match header("x-forwarded-proto") {
+            Some(scheme @ ("http" | "https")) => scheme,
+            _ => default_scheme,
+        };
+
+        let host = match header("x-forwarded-host") {
+            Some(host) if is_valid_host(host) => host,
+            _ => default_host,
+        };
+
+        Self(format!("{scheme}://{host}"))
+    }
+
+    pub fn is_https(&self) -> bool {
+        self.0.starts_with("https://")
+    }
+
+    /// Where Spotify sends users back to after they log in.
+    pub fn redirect_uri(&self) -> String {
+        format!("{}{CALLBACK_ENDPOINT}", self.0)
+    }
+}
+
+/// Only allow characters that can appear in a hostname, an IP address and a port.
+fn is_valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Origin {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        Ok(Self::resolve(crate::config::get(), peer, &parts.headers))
+    }
+}
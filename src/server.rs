@@ -0,0 +1,252 @@
+//! Assembles the axum application, shared by the server binary and integration tests.
+
+use std::{collections::HashSet, future::Future};
+
+use async_trait::async_trait;
+use axum::{
+    body::Body as AxumBody,
+    error_handling::HandleErrorLayer,
+    extract::{FromRef, FromRequestParts, Path, RawQuery, State},
+    handler::Handler,
+    http::StatusCode,
+    http::{header::SET_COOKIE, request::Parts, HeaderMap, HeaderValue, Request},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    BoxError, Router,
+};
+use axum_login::{
+    tower_sessions::{cookie::SameSite, Expiry, Session, SessionManagerLayer},
+    AuthManagerLayerBuilder,
+};
+use leptos::LeptosOptions;
+use leptos_axum::{generate_route_list, LeptosRoutes};
+use rspotify::{clients::BaseClient, AuthCodePkceSpotify, AuthCodeSpotify, Credentials, OAuth};
+use tower::ServiceBuilder;
+
+use crate::{
+    app::App,
+    auth::{self, AuthSession, Backend, SpotifyClient, StateSigner},
+    config::{AuthFlow, Config},
+    export,
+    origin::Origin,
+    session::Sessions,
+    store::SharedStore,
+    CALLBACK_ENDPOINT, EXPORT_ENDPOINT, LOGOUT_ENDPOINT, SPOTIFY_SCOPES,
+};
+
+#[derive(FromRef, Debug, Clone)]
+pub struct AppState {
+    pub leptos_options: LeptosOptions,
+    pub routes: Vec<leptos_router::RouteListing>,
+    pub spotify_credentials: Credentials,
+    pub store: SharedStore,
+}
+
+/// The base Spotify client every user's client is cloned from, for the configured [`AuthFlow`].
+pub fn spotify_client(config: &Config) -> SpotifyClient {
+    let creds = Credentials {
+        id: config.spotify.client_id.clone(),
+        secret: config.spotify.client_secret.clone(),
+    };
+
+    let oauth = OAuth {
+        redirect_uri: Origin(config.public_url.clone()).redirect_uri(),
+        scopes: HashSet::from(SPOTIFY_SCOPES.map(|s| s.into())),
+        ..Default::default()
+    };
+
+    let spotify_config = rspotify::Config {
+        api_base_url: config.spotify.api_base_url.clone(),
+        auth_base_url: config.spotify.auth_base_url.clone(),
+        token_cached: false,
+        ..Default::default()
+    };
+
+    match config.spotify.auth_flow {
+        AuthFlow::AuthCode => SpotifyClient::AuthCode(AuthCodeSpotify::with_config(creds, oauth, spotify_config)),
+        AuthFlow::Pkce => SpotifyClient::Pkce(AuthCodePkceSpotify::with_config(creds, oauth, spotify_config)),
+    }
+}
+
+/// The authentication backend, which also loads users outside of requests.
+pub fn backend(client: SpotifyClient, config: &Config, store: SharedStore) -> Backend {
+    Backend::new(client, StateSigner::new(config.state_secret.clone()), store)
+}
+
+/// Build the application: authentication, server functions and leptos routes,
+/// storing everything in `store`, with `fallback` handling everything else.
+pub fn router<H, T>(
+    config: &Config,
+    leptos_options: LeptosOptions,
+    store: SharedStore,
+    fallback: H,
+) -> Router
+where
+    H: Handler<T, AppState>,
+    T: 'static,
+{
+    let client = spotify_client(config);
+    let routes = generate_route_list(App);
+
+    let app_state = AppState {
+        leptos_options,
+        routes: routes.clone(),
+        spotify_credentials: client.get_creds().clone(),
+        store: store.clone(),
+    };
+
+    let backend = backend(client, config, store.clone());
+
+    // `secure_cookies` decides per request whether the cookie is Secure
+    let mut session_layer = SessionManagerLayer::new(Sessions::new(store))
+        .with_same_site(SameSite::Lax)
+        .with_secure(false)
+        .with_expiry(Expiry::OnInactivity(config.session_expiry));
+
+    if let Some(domain) = &config.cookie_domain {
+        session_layer = session_layer.with_domain(domain.clone());
+    }
+
+    Router::new()
+        .route(CALLBACK_ENDPOINT, get(auth::authorize))
+        .route(LOGOUT_ENDPOINT, post(auth::logout))
+        .route(&format!("{EXPORT_ENDPOINT}/constellation.svg"), get(export::constellation_svg))
+        .route(&format!("{EXPORT_ENDPOINT}/constellation.png"), get(export::constellation_png))
+        .route(EXPORT_ENDPOINT, get(export::data_json))
+        .route(&format!("{EXPORT_ENDPOINT}/data.json"), get(export::data_json))
+        .route(&format!("{EXPORT_ENDPOINT}/:file"), get(export::data_csv))
+        // server functions that use context after awaiting run their body through `with_request_runtime`
+        .route(
+            "/api/*fn_name",
+            get(server_fn_handler).post(server_fn_handler),
+        )
+        .leptos_routes_with_handler(routes, get(leptos_routes_handler))
+        .fallback(fallback)
+        .with_state(app_state)
+        .layer(ServiceBuilder::new()
+            .layer(HandleErrorLayer::new(|_: BoxError| async {
+                StatusCode::BAD_REQUEST
+            }))
+            .layer(AuthManagerLayerBuilder::new(backend, session_layer).build())
+        )
+        .layer(middleware::from_fn(secure_cookies))
+}
+
+/// Mark the cookies of requests made over HTTPS as Secure. Behind a trusted
+/// proxy that can differ per request, so it can't be configured up front.
+async fn secure_cookies(origin: Origin, request: Request<AxumBody>, next: Next<AxumBody>) -> Response {
+    let mut response = next.run(request).await;
+
+    if !origin.is_https() {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    let cookies: Vec<HeaderValue> = headers.get_all(SET_COOKIE).iter().cloned().collect();
+    headers.remove(SET_COOKIE);
+
+    for cookie in cookies {
+        let secure = cookie
+            .to_str()
+            .ok()
+            .filter(|cookie| !cookie.to_ascii_lowercase().contains("; secure"))
+            .and_then(|cookie| HeaderValue::from_str(&format!("{cookie}; Secure")).ok());
+
+        headers.append(SET_COOKIE, secure.unwrap_or(cookie));
+    }
+
+    response
+}
+
+/// Per-request values provided to leptos alongside [`AppState`].
+#[derive(Clone)]
+struct RequestContext {
+    auth_session: AuthSession,
+    session: Session,
+    origin: Origin,
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for RequestContext {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            auth_session: AuthSession::from_request_parts(parts, state)
+                .await
+                .map_err(IntoResponse::into_response)?,
+            session: Session::from_request_parts(parts, state)
+                .await
+                .map_err(IntoResponse::into_response)?,
+            origin: Origin::from_request_parts(parts, state)
+                .await
+                .map_err(|never| match never {})?,
+        })
+    }
+}
+
+/// Handle leptos routes and inject state for payload
+async fn leptos_routes_handler(
+    context: RequestContext,
+    State(app_state): State<AppState>,
+    req: Request<AxumBody>,
+) -> impl IntoResponse {
+    let handler = leptos_axum::render_route_with_context(
+        app_state.leptos_options.clone(),
+        app_state.routes.clone(),
+        move || provide_state_context(&context, &app_state),
+        App,
+    );
+
+    handler(req).await
+}
+
+/// Handle leptos server functions and inject state for pageload
+async fn server_fn_handler(
+    context: RequestContext,
+    State(app_state): State<AppState>,
+    path: Path<String>,
+    headers: HeaderMap,
+    raw_query: RawQuery,
+    req: Request<AxumBody>,
+) -> impl IntoResponse {
+    leptos_axum::handle_server_fns_with_context(
+        path,
+        headers,
+        raw_query,
+        move || provide_state_context(&context, &app_state),
+        req,
+    )
+    .await
+}
+
+/// Run `body` with the request's leptos runtime current whenever it's polled.
+///
+/// leptos_axum runs the server functions of several requests on one thread
+/// and keeps the current runtime in a thread local, which the others replace
+/// while this one awaits. Without this, `use_context` after an `.await`, and
+/// leptos reading the response options afterwards, may see another request's.
+///
+/// Server functions that only await [`crate::client::get_or_fetch`] after
+/// reading their context needn't, it runs through this itself.
+pub async fn with_request_runtime<T>(body: impl Future<Output = T>) -> T {
+    let runtime = leptos::current_runtime();
+    let mut body = std::pin::pin!(body);
+
+    std::future::poll_fn(|cx| {
+        leptos::set_current_runtime(runtime);
+        body.as_mut().poll(cx)
+    })
+    .await
+}
+
+/// Provide leptos context for each [`AppState`] and [`RequestContext`] field.
+fn provide_state_context(context: &RequestContext, app_state: &AppState) {
+    leptos::provide_context(app_state.spotify_credentials.clone());
+    leptos::provide_context(app_state.leptos_options.clone());
+    leptos::provide_context(app_state.store.clone());
+    leptos::provide_context(context.auth_session.clone());
+    leptos::provide_context(context.session.clone());
+    leptos::provide_context(context.origin.clone());
+}
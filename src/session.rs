@@ -0,0 +1,122 @@
+use async_trait::async_trait;
+use axum_login::tower_sessions::{
+    session::Id, session_store::ExpiredDeletion, Session, SessionStore,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::OffsetDateTime;
+
+use crate::store::{self, schema, SharedStore, Stored};
+
+/// Name of the tree sessions are stored in.
+pub const SESSIONS_TREE: &str = "sessions";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Store(#[from] store::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// A stored session alongside its expiry date.
+///
+/// The session itself is kept as JSON because its data map holds
+/// [`serde_json::Value`]s, which bincode can't deserialize.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SessionRecord {
+    expiry_date: i64,
+    session: Vec<u8>,
+}
+
+impl Stored for SessionRecord {
+    const TAG: &'static str = "session";
+}
+
+impl SessionRecord {
+    fn is_active(&self) -> bool {
+        self.expiry_date > OffsetDateTime::now_utc().unix_timestamp()
+    }
+}
+
+/// A [`SessionStore`] persisting sessions in their own tree of a [`store::Store`],
+/// so logins survive server restarts.
+#[derive(Clone, Debug)]
+pub struct Sessions {
+    store: SharedStore,
+}
+
+impl Sessions {
+    pub fn new(store: SharedStore) -> Self {
+        Self { store }
+    }
+
+    fn get_record(&self, session_id: &Id) -> Result<Option<SessionRecord>, Error> {
+        Ok(store::get_value(self.store.as_ref(), SESSIONS_TREE, &session_id.0.to_string())?)
+    }
+}
+
+#[async_trait]
+impl SessionStore for Sessions {
+    type Error = Error;
+
+    async fn save(&self, session: &Session) -> Result<(), Self::Error> {
+        let record = SessionRecord {
+            expiry_date: session.expiry_date().unix_timestamp(),
+            session: serde_json::to_vec(session)?,
+        };
+
+        self.store
+            .put(SESSIONS_TREE, &session.id().0.to_string(), schema::encode(&record), None)?;
+
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> Result<Option<Session>, Self::Error> {
+        match self.get_record(session_id)? {
+            Some(record) if record.is_active() => Ok(Some(serde_json::from_slice(&record.session)?)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> Result<(), Self::Error> {
+        self.store.delete(SESSIONS_TREE, &session_id.0.to_string())?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for Sessions {
+    async fn delete_expired(&self) -> Result<(), Self::Error> {
+        for (key, bytes) in self.store.scan_prefix(SESSIONS_TREE, "")? {
+            // remove records that are expired or can no longer be read
+            let expired = schema::decode::<SessionRecord>(&bytes)
+                .map(|record| !record.is_active())
+                .unwrap_or(true);
+
+            if expired {
+                self.store.delete(SESSIONS_TREE, &key)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Delete expired sessions from `store` every `period`, forever.
+///
+/// Unlike tower-sessions' own `continuously_delete_expired`, which ends at the
+/// first error, errors are logged and deletion is tried again next period.
+pub async fn continuously_delete_expired(store: Sessions, period: std::time::Duration) {
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(err) = store.delete_expired().await {
+            tracing::error!("Error deleting expired sessions: {err}");
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Read-only snapshots of a constellation that anyone with the link can view.
+
+use leptos::*;
+use rspotify::model::TimeRange;
+use serde::{Deserialize, Serialize};
+
+use crate::constellation::Constellation;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "ssr")] {
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+        use rand::RngCore;
+
+        use crate::store::{self, SharedStore, Store, Stored};
+
+        /// Name of the tree snapshots are stored in by ID.
+        pub const SNAPSHOTS_TREE: &str = "snapshots";
+        /// Name of the tree holding every snapshot ID under `{owner}/{id}`, so a user's snapshots can be listed and deleted.
+        pub const SNAPSHOT_OWNERS_TREE: &str = "snapshot_owners";
+
+        /// A [`Snapshot`] along with who may revoke it.
+        #[derive(Serialize, Deserialize)]
+        pub(crate) struct SnapshotRecord {
+            pub(crate) owner: String,
+            snapshot: Snapshot,
+        }
+
+        impl Stored for SnapshotRecord {
+            const TAG: &'static str = "shared_snapshot";
+        }
+
+        /// A random, unguessable snapshot ID.
+        fn new_snapshot_id() -> String {
+            let mut id = [0u8; 16];
+            rand::thread_rng().fill_bytes(&mut id);
+
+            URL_SAFE_NO_PAD.encode(id)
+        }
+
+        fn get_record(store: &dyn Store, id: &str) -> Result<Option<SnapshotRecord>, store::Error> {
+            store::get_value(store, SNAPSHOTS_TREE, id)
+        }
+
+        /// Remove every snapshot `user_id` shared.
+        pub fn delete_user_snapshots(store: &dyn Store, user_id: &str) -> Result<(), store::Error> {
+            let prefix = store::user_prefix(user_id);
+
+            for (key, _) in store.scan_prefix(SNAPSHOT_OWNERS_TREE, &prefix)? {
+                if let Some(id) = key.strip_prefix(&prefix) {
+                    store.delete(SNAPSHOTS_TREE, id)?;
+                }
+
+                store.delete(SNAPSHOT_OWNERS_TREE, &key)?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+/// A constellation frozen at the moment it was shared.
+///
+/// Only what the owner chose to expose is stored, so a snapshot never
+/// reveals more than its owner saw when they shared it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub constellation: Constellation,
+    /// The owner's display name, if they chose to show it.
+    pub display_name: Option<String>,
+    /// The time range the constellation covers, if they chose to show it.
+    pub range: Option<TimeRange>,
+    /// When the snapshot was taken, as a unix timestamp.
+    pub created_at: i64,
+}
+
+/// What to include in a new [`Snapshot`] besides the constellation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareOptions {
+    pub show_name: bool,
+    pub show_range: bool,
+}
+
+/// Freeze the current user's constellation for `range` into a new snapshot, returning its ID.
+#[server]
+pub async fn share_constellation(range: TimeRange, options: ShareOptions) -> Result<Option<String>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        crate::server::with_request_runtime(async move {
+            use crate::{auth::AuthSession, client::get_current_user, constellation::get_constellation};
+
+            let Some(user) = use_context::<AuthSession>()
+                .expect("no auth session provided")
+                .user else {
+                    return Ok(None);
+                };
+
+            let Some(constellation) = get_constellation(range).await? else {
+                return Ok(None);
+            };
+
+            let display_name = match options.show_name {
+                true => get_current_user().await?.and_then(|me| me.display_name),
+                false => None,
+            };
+
+            let snapshot = Snapshot {
+                id: new_snapshot_id(),
+                constellation,
+                display_name,
+                range: options.show_range.then_some(range),
+                created_at: time::OffsetDateTime::now_utc().unix_timestamp(),
+            };
+
+            let id = snapshot.id.clone();
+            let record = SnapshotRecord {
+                owner: user.user_id.clone(),
+                snapshot,
+            };
+
+            let store = use_context::<SharedStore>().expect("no store provided");
+
+            store
+                .put(SNAPSHOTS_TREE, &id, store::schema::encode(&record), None)
+                .and_then(|_| store.put(SNAPSHOT_OWNERS_TREE, &store::user_key(&user.user_id, &id), Vec::new(), None))
+                .map_err(|err| ServerFnError::ServerError(format!("Error saving snapshot: {err}")))?;
+
+            Ok(Some(id))
+        })
+        .await
+    }
+}
+
+/// A shared snapshot by ID, for anyone, logged in or not.
+#[server]
+pub async fn get_snapshot(id: String) -> Result<Option<Snapshot>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        let store = use_context::<SharedStore>().expect("no store provided");
+
+        get_record(&*store, &id)
+            .map(|record| record.map(|record| record.snapshot))
+            .map_err(|err| ServerFnError::ServerError(format!("Error reading snapshot: {err}")))
+    }
+}
+
+/// Every snapshot the current user has shared, newest first.
+#[server]
+pub async fn get_my_snapshots() -> Result<Option<Vec<Snapshot>>, ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        let Some(user) = use_context::<crate::auth::AuthSession>()
+            .expect("no auth session provided")
+            .user else {
+                return Ok(None);
+            };
+
+        let store = use_context::<SharedStore>().expect("no store provided");
+        let prefix = crate::store::user_prefix(&user.user_id);
+        let mut snapshots = Vec::new();
+
+        let owned = store
+            .scan_prefix(SNAPSHOT_OWNERS_TREE, &prefix)
+            .map_err(|err| ServerFnError::ServerError(format!("Error reading snapshots: {err}")))?;
+
+        for (key, _) in owned {
+            let id = &key[prefix.len()..];
+
+            if let Some(record) = get_record(&*store, id).map_err(|err| ServerFnError::ServerError(format!("Error reading snapshot: {err}")))? {
+                snapshots.push(record.snapshot);
+            }
+        }
+
+        snapshots.sort_by_key(|snapshot| std::cmp::Reverse(snapshot.created_at));
+
+        Ok(Some(snapshots))
+    }
+}
+
+/// Delete a snapshot the current user shared, so its link stops working.
+#[server]
+pub async fn revoke_snapshot(id: String) -> Result<(), ServerFnError> {
+    #[cfg(feature = "ssr")]
+    {
+        let Some(user) = use_context::<crate::auth::AuthSession>()
+            .expect("no auth session provided")
+            .user else {
+                return Err(ServerFnError::ServerError("Not logged in".to_string()));
+            };
+
+        let store = use_context::<SharedStore>().expect("no store provided");
+
+        let record = get_record(&*store, &id)
+            .map_err(|err| ServerFnError::ServerError(format!("Error reading snapshot: {err}")))?;
+
+        // revoking someone else's snapshot looks the same as revoking a missing one
+        if !record.is_some_and(|record| record.owner == user.user_id) {
+            return Err(ServerFnError::ServerError("No such snapshot".to_string()));
+        }
+
+        store
+            .delete(SNAPSHOTS_TREE, &id)
+            .and_then(|_| store.delete(SNAPSHOT_OWNERS_TREE, &crate::store::user_key(&user.user_id, &id)))
+            .map_err(|err| ServerFnError::ServerError(format!("Error deleting snapshot: {err}")))
+    }
+}
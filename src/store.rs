@@ -0,0 +1,84 @@
+//! Key-value storage behind a [`Store`] trait, so the server can run against
+//! sled on disk or, in tests, entirely in memory.
+//!
+//! Entries live in named trees, like sled's, and every tree is sorted by key
+//! so prefixes can be scanned.
+
+use std::{fmt, sync::Arc};
+
+use thiserror::Error;
+use time::{Duration, OffsetDateTime};
+
+mod disk;
+mod memory;
+pub mod migrations;
+pub mod schema;
+
+pub use disk::SledStore;
+pub use memory::MemoryStore;
+pub use schema::{get_value, put_value, Stored};
+
+/// The tree tokens and other per-user records are stored in.
+pub const DEFAULT_TREE: &str = "default";
+
+/// Separates a user ID from the rest of a per-user key. Spotify IDs never
+/// contain it, so one user's keys are never a prefix of another's.
+pub const USER_KEY_SEPARATOR: char = '/';
+
+/// The key `name` of `user_id`, e.g. `spotify:user:alice/refreshed`.
+pub fn user_key(user_id: &str, name: &str) -> String {
+    format!("{user_id}{USER_KEY_SEPARATOR}{name}")
+}
+
+/// The prefix every [`user_key`] of `user_id` starts with.
+pub fn user_prefix(user_id: &str) -> String {
+    format!("{user_id}{USER_KEY_SEPARATOR}")
+}
+
+/// The store shared by the whole server, provided to server functions as leptos context.
+pub type SharedStore = Arc<dyn Store>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Sled(#[from] sled::Error),
+
+    #[error("store has schema version {found}, but this release only knows up to {supported}")]
+    UnknownSchema { found: u32, supported: u32 },
+}
+
+/// Byte values under string keys, split into named trees.
+///
+/// Entries put with a TTL disappear from [`Store::get`] and
+/// [`Store::scan_prefix`] once it runs out, and are removed for good by
+/// [`Store::prune_expired`].
+pub trait Store: fmt::Debug + Send + Sync {
+    fn get(&self, tree: &str, key: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Store `value` under `key`, forgetting it after `ttl` if there is one.
+    fn put(&self, tree: &str, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), Error>;
+
+    /// Replace the value under `key` with `new` only if it's still `old`,
+    /// where `None` means missing. Returns whether it was replaced.
+    fn compare_and_swap(&self, tree: &str, key: &str, old: Option<&[u8]>, new: Option<Vec<u8>>) -> Result<bool, Error>;
+
+    fn delete(&self, tree: &str, key: &str) -> Result<(), Error>;
+
+    /// Remove every entry of `tree`.
+    fn clear(&self, tree: &str) -> Result<(), Error>;
+
+    /// Every entry whose key starts with `prefix`, sorted by key.
+    fn scan_prefix(&self, tree: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, Error>;
+
+    /// Remove every expired entry, returning how many were removed.
+    fn prune_expired(&self) -> Result<usize, Error>;
+}
+
+/// When an entry put now with `ttl` expires, as a unix timestamp.
+fn expires_at(ttl: Duration) -> i64 {
+    (OffsetDateTime::now_utc() + ttl).unix_timestamp()
+}
+
+fn is_expired(expires_at: i64) -> bool {
+    expires_at <= OffsetDateTime::now_utc().unix_timestamp()
+}
@@ -0,0 +1,137 @@
+use std::path::Path;
+
+use time::Duration;
+
+use super::{expires_at, is_expired, Error, Store, DEFAULT_TREE};
+
+/// Suffix of the tree holding the expiry of every entry with a TTL in a tree.
+const EXPIRY_SUFFIX: &str = ".expires";
+
+/// A [`Store`] persisted to disk with sled.
+///
+/// Values are stored as they are, so trees stay readable without the store.
+/// Expiry times are kept in a separate tree next to each tree that has any.
+#[derive(Clone, Debug)]
+pub struct SledStore {
+    db: sled::Db,
+}
+
+impl SledStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Ok(Self { db: sled::open(path)? })
+    }
+
+    fn tree(&self, name: &str) -> Result<sled::Tree, Error> {
+        match name {
+            DEFAULT_TREE => Ok(sled::Tree::clone(&self.db)),
+            name => Ok(self.db.open_tree(name)?),
+        }
+    }
+
+    fn expiry_tree(&self, name: &str) -> Result<sled::Tree, Error> {
+        Ok(self.db.open_tree(format!("{name}{EXPIRY_SUFFIX}"))?)
+    }
+
+    /// Whether `key` has expired, removing it if so.
+    fn remove_if_expired(&self, tree: &sled::Tree, expiry: &sled::Tree, key: &[u8]) -> Result<bool, Error> {
+        let expired = expiry
+            .get(key)?
+            .and_then(|out| out.as_ref().try_into().ok().map(i64::from_be_bytes))
+            .is_some_and(is_expired);
+
+        if expired {
+            tree.remove(key)?;
+            expiry.remove(key)?;
+        }
+
+        Ok(expired)
+    }
+}
+
+impl Store for SledStore {
+    fn get(&self, tree: &str, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let (tree, expiry) = (self.tree(tree)?, self.expiry_tree(tree)?);
+
+        if self.remove_if_expired(&tree, &expiry, key.as_bytes())? {
+            return Ok(None);
+        }
+
+        Ok(tree.get(key)?.map(|out| out.to_vec()))
+    }
+
+    fn put(&self, tree: &str, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), Error> {
+        let (tree, expiry) = (self.tree(tree)?, self.expiry_tree(tree)?);
+
+        match ttl {
+            Some(ttl) => expiry.insert(key, &expires_at(ttl).to_be_bytes())?,
+            None => expiry.remove(key)?,
+        };
+
+        tree.insert(key, value)?;
+
+        Ok(())
+    }
+
+    fn compare_and_swap(&self, tree: &str, key: &str, old: Option<&[u8]>, new: Option<Vec<u8>>) -> Result<bool, Error> {
+        let (tree, expiry) = (self.tree(tree)?, self.expiry_tree(tree)?);
+
+        self.remove_if_expired(&tree, &expiry, key.as_bytes())?;
+
+        let swapped = tree.compare_and_swap(key, old, new)?.is_ok();
+
+        if swapped {
+            expiry.remove(key)?;
+        }
+
+        Ok(swapped)
+    }
+
+    fn delete(&self, tree: &str, key: &str) -> Result<(), Error> {
+        self.tree(tree)?.remove(key)?;
+        self.expiry_tree(tree)?.remove(key)?;
+
+        Ok(())
+    }
+
+    fn clear(&self, tree: &str) -> Result<(), Error> {
+        self.tree(tree)?.clear()?;
+        self.expiry_tree(tree)?.clear()?;
+
+        Ok(())
+    }
+
+    fn scan_prefix(&self, tree: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        let (tree, expiry) = (self.tree(tree)?, self.expiry_tree(tree)?);
+        let mut entries = Vec::new();
+
+        for entry in tree.scan_prefix(prefix) {
+            let (key, value) = entry?;
+
+            if !self.remove_if_expired(&tree, &expiry, &key)? {
+                entries.push((String::from_utf8_lossy(&key).into_owned(), value.to_vec()));
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn prune_expired(&self) -> Result<usize, Error> {
+        let mut pruned = 0;
+
+        for name in self.db.tree_names() {
+            let Some(name) = std::str::from_utf8(&name).ok().and_then(|name| name.strip_suffix(EXPIRY_SUFFIX)) else {
+                continue;
+            };
+
+            let (tree, expiry) = (self.tree(name)?, self.expiry_tree(name)?);
+
+            for key in expiry.iter().keys() {
+                if self.remove_if_expired(&tree, &expiry, &key?)? {
+                    pruned += 1;
+                }
+            }
+        }
+
+        Ok(pruned)
+    }
+}
@@ -0,0 +1,115 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use time::Duration;
+
+use super::{expires_at, is_expired, Error, Store};
+
+/// A value along with when it expires, if it does.
+#[derive(Debug)]
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<i64>,
+}
+
+impl Entry {
+    fn is_live(&self) -> bool {
+        !self.expires_at.is_some_and(is_expired)
+    }
+}
+
+/// A [`Store`] that only lives as long as the process, for tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    trees: Mutex<HashMap<String, BTreeMap<String, Entry>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_tree<T>(&self, tree: &str, f: impl FnOnce(&mut BTreeMap<String, Entry>) -> T) -> T {
+        let mut trees = self.trees.lock().expect("lock memory store");
+
+        f(trees.entry(tree.to_string()).or_default())
+    }
+}
+
+impl Store for MemoryStore {
+    fn get(&self, tree: &str, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.with_tree(tree, |tree| {
+            tree.get(key)
+                .filter(|entry| entry.is_live())
+                .map(|entry| entry.value.clone())
+        }))
+    }
+
+    fn put(&self, tree: &str, key: &str, value: Vec<u8>, ttl: Option<Duration>) -> Result<(), Error> {
+        self.with_tree(tree, |tree| {
+            tree.insert(key.to_string(), Entry {
+                value,
+                expires_at: ttl.map(expires_at),
+            })
+        });
+
+        Ok(())
+    }
+
+    fn compare_and_swap(&self, tree: &str, key: &str, old: Option<&[u8]>, new: Option<Vec<u8>>) -> Result<bool, Error> {
+        Ok(self.with_tree(tree, |tree| {
+            let current = tree
+                .get(key)
+                .filter(|entry| entry.is_live())
+                .map(|entry| entry.value.as_slice());
+
+            if current != old {
+                return false;
+            }
+
+            match new {
+                Some(value) => tree.insert(key.to_string(), Entry { value, expires_at: None }),
+                None => tree.remove(key),
+            };
+
+            true
+        }))
+    }
+
+    fn delete(&self, tree: &str, key: &str) -> Result<(), Error> {
+        self.with_tree(tree, |tree| tree.remove(key));
+
+        Ok(())
+    }
+
+    fn clear(&self, tree: &str) -> Result<(), Error> {
+        self.with_tree(tree, |tree| tree.clear());
+
+        Ok(())
+    }
+
+    fn scan_prefix(&self, tree: &str, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, Error> {
+        Ok(self.with_tree(tree, |tree| {
+            tree.range(prefix.to_string()..)
+                .take_while(|(key, _)| key.starts_with(prefix))
+                .filter(|(_, entry)| entry.is_live())
+                .map(|(key, entry)| (key.clone(), entry.value.clone()))
+                .collect()
+        }))
+    }
+
+    fn prune_expired(&self) -> Result<usize, Error> {
+        let mut trees = self.trees.lock().expect("lock memory store");
+        let mut pruned = 0;
+
+        for tree in trees.values_mut() {
+            let before = tree.len();
+            tree.retain(|_, entry| entry.is_live());
+            pruned += before - tree.len();
+        }
+
+        Ok(pruned)
+    }
+}
@@ -0,0 +1,287 @@
+//! Upgrades of stored data between releases, run on startup and by `starify migrate`.
+
+use std::{collections::BTreeMap, fmt};
+
+use super::{
+    schema::{self, Stored, Tagged},
+    user_key, Error, MemoryStore, Store, DEFAULT_TREE, USER_KEY_SEPARATOR,
+};
+use crate::{
+    auth::tokens,
+    client::CACHE_TREE,
+    history::{HistorySnapshot, HISTORY_TREE},
+    session::{SessionRecord, SESSIONS_TREE},
+    share::{SnapshotRecord, SNAPSHOTS_TREE, SNAPSHOT_OWNERS_TREE},
+};
+
+/// The tree holding [`SCHEMA_VERSION_KEY`].
+pub const META_TREE: &str = "meta";
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+/// Every tree starify stores data in, which a dry run copies.
+const TREES: &[&str] = &[
+    DEFAULT_TREE,
+    CACHE_TREE,
+    HISTORY_TREE,
+    SESSIONS_TREE,
+    SNAPSHOTS_TREE,
+    SNAPSHOT_OWNERS_TREE,
+    META_TREE,
+];
+
+/// The schema version this release reads and writes.
+pub const SCHEMA_VERSION: u32 = MIGRATIONS[MIGRATIONS.len() - 1].version;
+
+/// A step from the previous schema version to `version`.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    run: fn(&mut Migrator) -> Result<(), Error>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Tag every stored value with its type and version",
+        run: tag_values,
+    },
+    Migration {
+        version: 2,
+        description: "Remove responses cached in the default tree",
+        run: remove_legacy_cache,
+    },
+    Migration {
+        version: 3,
+        description: "Separate user IDs from the rest of their keys",
+        run: separate_user_keys,
+    },
+];
+
+/// What a migration changed, or would change in a dry run, in one tree.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TreeChanges {
+    pub rewritten: usize,
+    pub removed: usize,
+}
+
+/// What running a migration changed, or would change in a dry run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub version: u32,
+    pub description: &'static str,
+    pub changes: BTreeMap<String, TreeChanges>,
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.version, self.description)?;
+
+        if self.changes.is_empty() {
+            return write!(f, " (nothing to change)");
+        }
+
+        for (tree, changes) in &self.changes {
+            write!(f, "\n  {tree}: {} rewritten, {} removed", changes.rewritten, changes.removed)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Applies the changes of a [`Migration`], counting them.
+struct Migrator<'a> {
+    store: &'a dyn Store,
+    changes: BTreeMap<String, TreeChanges>,
+}
+
+impl Migrator<'_> {
+    fn changes(&mut self, tree: &str) -> &mut TreeChanges {
+        self.changes.entry(tree.to_string()).or_default()
+    }
+
+    fn rewrite(&mut self, tree: &str, key: &str, value: Vec<u8>) -> Result<(), Error> {
+        self.changes(tree).rewritten += 1;
+        self.store.put(tree, key, value, None)
+    }
+
+    fn remove(&mut self, tree: &str, key: &str) -> Result<(), Error> {
+        self.changes(tree).removed += 1;
+        self.store.delete(tree, key)
+    }
+
+    fn rename(&mut self, tree: &str, key: &str, new_key: &str, value: Vec<u8>) -> Result<(), Error> {
+        self.changes(tree).rewritten += 1;
+        self.store.put(tree, new_key, value, None)?;
+        self.store.delete(tree, key)
+    }
+
+    fn clear(&mut self, tree: &str) -> Result<(), Error> {
+        let count = self.store.scan_prefix(tree, "")?.len();
+
+        if count == 0 {
+            return Ok(());
+        }
+
+        self.changes(tree).removed += count;
+        self.store.clear(tree)
+    }
+
+    /// Tag every untagged `V` whose key matches `filter`, removing the ones that can't be read.
+    fn tag_untagged<V: Stored>(&mut self, tree: &str, filter: impl Fn(&str) -> bool) -> Result<(), Error> {
+        for (key, bytes) in self.store.scan_prefix(tree, "")? {
+            if !filter(&key) || Tagged::parse(&bytes).is_ok() {
+                continue;
+            }
+
+            match bincode::deserialize::<V>(&bytes) {
+                Ok(value) => self.rewrite(tree, &key, schema::encode(&value))?,
+                Err(err) => {
+                    tracing::warn!("Removing unreadable {tree} entry {key}: {err}");
+                    self.remove(tree, &key)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Version 1: values used to be plain bincode.
+///
+/// Cached responses can't tell what type they hold, so they're dropped.
+/// Tokens keep their own format.
+fn tag_values(migrator: &mut Migrator) -> Result<(), Error> {
+    migrator.clear(CACHE_TREE)?;
+
+    migrator.tag_untagged::<SessionRecord>(SESSIONS_TREE, |_| true)?;
+    migrator.tag_untagged::<i64>(DEFAULT_TREE, |key| key.ends_with("_refreshed"))?;
+    migrator.tag_untagged::<HistorySnapshot>(HISTORY_TREE, |_| true)?;
+    migrator.tag_untagged::<SnapshotRecord>(SNAPSHOTS_TREE, |_| true)?;
+
+    Ok(())
+}
+
+/// Version 2: responses used to be cached in [`DEFAULT_TREE`], under
+/// `{user_id}_userinfo`, `{user_id}_topartists_{range}` and
+/// `{user_id}_constellation_{range}`, and never expired.
+///
+/// A user ID can end like one of those keys too, so entries that read as a
+/// token are left alone.
+fn remove_legacy_cache(migrator: &mut Migrator) -> Result<(), Error> {
+    let is_legacy_key = |key: &str| {
+        key.ends_with("_userinfo")
+            || key.rsplit_once('_').is_some_and(|(kind, range)| {
+                matches!(range, "ShortTerm" | "MediumTerm" | "LongTerm")
+                    && (kind.ends_with("_topartists") || kind.ends_with("_constellation"))
+            })
+    };
+
+    for (key, bytes) in migrator.store.scan_prefix(DEFAULT_TREE, "")? {
+        if is_legacy_key(&key) && !tokens::is_token_entry(&bytes) {
+            migrator.remove(DEFAULT_TREE, &key)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Version 3: per-user keys used to start with `{user_id}_`, which is also
+/// how the keys of a user whose ID continues with `_` start.
+///
+/// Cached responses are dropped, the rest move to [`user_key`]s. Who owns a
+/// shared snapshot is read from the snapshot itself.
+fn separate_user_keys(migrator: &mut Migrator) -> Result<(), Error> {
+    migrator.clear(CACHE_TREE)?;
+
+    for (key, bytes) in migrator.store.scan_prefix(DEFAULT_TREE, "")? {
+        let Some(user_id) = key.strip_suffix("_refreshed") else {
+            continue;
+        };
+
+        // a token of someone whose ID ends like that doesn't read as a timestamp
+        if schema::decode::<i64>(&bytes).is_ok() {
+            migrator.rename(DEFAULT_TREE, &key, &user_key(user_id, "refreshed"), bytes)?;
+        }
+    }
+
+    for (key, bytes) in migrator.store.scan_prefix(HISTORY_TREE, "")? {
+        // user IDs may contain underscores, ranges and dates don't
+        let mut parts = key.rsplitn(3, '_');
+        let (Some(date), Some(range), Some(user_id)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+
+        if !user_id.contains(USER_KEY_SEPARATOR) {
+            migrator.rename(HISTORY_TREE, &key, &user_key(user_id, &format!("{range}_{date}")), bytes)?;
+        }
+    }
+
+    for (key, _) in migrator.store.scan_prefix(SNAPSHOT_OWNERS_TREE, "")? {
+        if !key.contains(USER_KEY_SEPARATOR) {
+            migrator.remove(SNAPSHOT_OWNERS_TREE, &key)?;
+        }
+    }
+
+    for (id, bytes) in migrator.store.scan_prefix(SNAPSHOTS_TREE, "")? {
+        if let Some(record) = schema::decode_or_log::<SnapshotRecord>(SNAPSHOTS_TREE, &id, &bytes) {
+            migrator.rewrite(SNAPSHOT_OWNERS_TREE, &user_key(&record.owner, &id), Vec::new())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The schema version of the data in `store`. A new store has version 0.
+pub fn schema_version(store: &dyn Store) -> Result<u32, Error> {
+    Ok(store
+        .get(META_TREE, SCHEMA_VERSION_KEY)?
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u32::from_be_bytes)
+        .unwrap_or(0))
+}
+
+/// Run every migration `store` hasn't had yet, in order. With `dry_run`,
+/// nothing is changed and the reports show what would be.
+///
+/// Refuses to touch a store written by a newer release.
+pub fn migrate(store: &dyn Store, dry_run: bool) -> Result<Vec<MigrationReport>, Error> {
+    if dry_run {
+        // migrate a copy for real, so each migration sees what the ones before it changed
+        let copy = MemoryStore::new();
+
+        for tree in TREES {
+            for (key, value) in store.scan_prefix(tree, "")? {
+                copy.put(tree, &key, value, None)?;
+            }
+        }
+
+        return migrate(&copy, false);
+    }
+
+    let found = schema_version(store)?;
+
+    if found > SCHEMA_VERSION {
+        return Err(Error::UnknownSchema { found, supported: SCHEMA_VERSION });
+    }
+
+    let mut reports = Vec::new();
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > found) {
+        let mut migrator = Migrator {
+            store,
+            changes: BTreeMap::new(),
+        };
+
+        (migration.run)(&mut migrator)?;
+
+        store.put(META_TREE, SCHEMA_VERSION_KEY, migration.version.to_be_bytes().to_vec(), None)?;
+
+        reports.push(MigrationReport {
+            version: migration.version,
+            description: migration.description,
+            changes: migrator.changes,
+        });
+    }
+
+    Ok(reports)
+}
@@ -0,0 +1,124 @@
+//! How values are encoded in a [`Store`].
+//!
+//! Every value starts with [`MAGIC`], the version of its type's encoding and
+//! its type's tag, followed by its bincode. Values that don't match what
+//! they're read as, e.g. because they were written by an older release, are
+//! logged and treated as missing instead of being misread.
+
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+use time::Duration;
+
+use super::{Error as StoreError, Store};
+
+/// Start of every tagged value.
+const MAGIC: &[u8; 3] = b"STV";
+
+/// A type that can be stored as a tagged value.
+pub trait Stored: Serialize + DeserializeOwned {
+    /// Identifies the type, so a value is never read back as another type.
+    const TAG: &'static str;
+    /// Bump this whenever the type's bincode changes, including through a
+    /// dependency like rspotify, so values of the old layout become misses.
+    const VERSION: u16 = 1;
+}
+
+impl Stored for i64 {
+    const TAG: &'static str = "i64";
+}
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("value has no schema tag")]
+    Untagged,
+
+    #[error("expected a {expected} value, found {found}")]
+    WrongTag { expected: &'static str, found: String },
+
+    #[error("expected version {expected} of {tag}, found version {found}")]
+    WrongVersion { tag: &'static str, expected: u16, found: u16 },
+
+    #[error("value is corrupt: {0}")]
+    Corrupt(#[from] bincode::Error),
+}
+
+/// A tagged value split into its tag, version and bincode.
+pub struct Tagged<'a> {
+    pub tag: &'a str,
+    pub version: u16,
+    pub payload: &'a [u8],
+}
+
+impl<'a> Tagged<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        let rest = bytes.strip_prefix(MAGIC).ok_or(DecodeError::Untagged)?;
+
+        let [version_high, version_low, tag_len, rest @ ..] = rest else {
+            return Err(DecodeError::Untagged);
+        };
+
+        if rest.len() < *tag_len as usize {
+            return Err(DecodeError::Untagged);
+        }
+
+        let (tag, payload) = rest.split_at(*tag_len as usize);
+
+        Ok(Self {
+            tag: std::str::from_utf8(tag).map_err(|_| DecodeError::Untagged)?,
+            version: u16::from_be_bytes([*version_high, *version_low]),
+            payload,
+        })
+    }
+}
+
+pub fn encode<V: Stored>(value: &V) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 3 + V::TAG.len());
+
+    bytes.extend(MAGIC);
+    bytes.extend(V::VERSION.to_be_bytes());
+    bytes.push(V::TAG.len() as u8);
+    bytes.extend(V::TAG.as_bytes());
+
+    bincode::serialize_into(&mut bytes, value).expect("parse to bincode");
+
+    bytes
+}
+
+pub fn decode<V: Stored>(bytes: &[u8]) -> Result<V, DecodeError> {
+    let tagged = Tagged::parse(bytes)?;
+
+    if tagged.tag != V::TAG {
+        return Err(DecodeError::WrongTag { expected: V::TAG, found: tagged.tag.to_string() });
+    }
+
+    if tagged.version != V::VERSION {
+        return Err(DecodeError::WrongVersion { tag: V::TAG, expected: V::VERSION, found: tagged.version });
+    }
+
+    Ok(bincode::deserialize(tagged.payload)?)
+}
+
+/// Get the value under `key`, treating one that can't be decoded as missing.
+pub fn get_value<V: Stored>(store: &dyn Store, tree: &str, key: &str) -> Result<Option<V>, StoreError> {
+    let Some(bytes) = store.get(tree, key)? else {
+        return Ok(None);
+    };
+
+    Ok(decode_or_log(tree, key, &bytes))
+}
+
+/// Store `value` under `key`, forgetting it after `ttl` if there is one.
+pub fn put_value<V: Stored>(store: &dyn Store, tree: &str, key: &str, value: &V, ttl: Option<Duration>) -> Result<(), StoreError> {
+    store.put(tree, key, encode(value), ttl)
+}
+
+/// Decode a value read from `key`, logging it if it can't be.
+pub fn decode_or_log<V: Stored>(tree: &str, key: &str, bytes: &[u8]) -> Option<V> {
+    match decode(bytes) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            tracing::warn!("Ignoring {tree} entry {key}: {err}");
+            None
+        }
+    }
+}
@@ -0,0 +1,56 @@
+mod common;
+
+use rspotify::model::TimeRange;
+
+use common::TestClient;
+use starify::{
+    client::{DeleteMyData, GetTopArtists, CACHE_TREE},
+    history,
+    share::{GetMySnapshots, ShareConstellation, Snapshot},
+    store::{user_prefix, DEFAULT_TREE},
+};
+
+/// Log in as `user`, cache their top artists, start their history and share a snapshot.
+async fn active_user(user: &str) -> TestClient {
+    let mut client = TestClient::new();
+    client.login(user).await;
+
+    client.server_fn::<GetTopArtists>("range=short_term").await;
+    client
+        .server_fn::<ShareConstellation>("range=short_term&options[show_name]=false&options[show_range]=false")
+        .await;
+
+    client
+}
+
+async fn my_snapshots(client: &mut TestClient) -> Option<Vec<Snapshot>> {
+    let response = client.server_fn::<GetMySnapshots>("").await;
+
+    serde_json::from_str(&response.body).unwrap()
+}
+
+#[tokio::test]
+async fn deleting_data_keeps_users_whose_id_extends_it() {
+    let mock = common::setup();
+    let store = common::store();
+
+    let mut john = active_user("john").await;
+    let mut john_smith = active_user("john_smith").await;
+
+    john.server_fn::<DeleteMyData>("").await;
+
+    let john_id = "spotify:user:john";
+    assert_eq!(store.get(DEFAULT_TREE, john_id).unwrap(), None);
+    assert!(store.scan_prefix(CACHE_TREE, &user_prefix(john_id)).unwrap().is_empty());
+    assert!(history::snapshots(&*store, john_id, TimeRange::ShortTerm).unwrap().is_empty());
+    assert_eq!(my_snapshots(&mut john).await, None);
+
+    let smith_id = "spotify:user:john_smith";
+    assert!(store.get(DEFAULT_TREE, smith_id).unwrap().is_some());
+    assert_eq!(history::snapshots(&*store, smith_id, TimeRange::ShortTerm).unwrap().len(), 1);
+    assert_eq!(my_snapshots(&mut john_smith).await.map(|snapshots| snapshots.len()), Some(1));
+
+    // still cached
+    john_smith.server_fn::<GetTopArtists>("range=short_term").await;
+    assert_eq!(mock.calls("john_smith", "/v1/me/top/artists"), 1);
+}
@@ -0,0 +1,256 @@
+mod common;
+
+use std::net::SocketAddr;
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{Request, StatusCode},
+};
+use rspotify::model::{FullArtist, PrivateUser};
+
+use common::TestClient;
+use starify::{
+    app::Login,
+    auth::MAX_PENDING_STATES,
+    client::{GetCurrentUser, GetFollowedArtists, GetTopArtists, GetTopTracks, TopTrack},
+    constellation::{
+        genres::{GetGenreBreakdown, RangeGenres},
+        Constellation, EdgeKind, GetConstellation,
+    },
+};
+
+#[tokio::test]
+async fn login_redirects_to_dashboard() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+
+    let response = client.login("login-user").await;
+
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(response.location.as_deref(), Some("/dashboard"));
+    assert_eq!(mock.calls("login-user", "/api/token"), 1);
+
+    let me = client.server_fn::<GetCurrentUser>("").await;
+    let me: Option<PrivateUser> = serde_json::from_str(&me.body).unwrap();
+
+    assert_eq!(me.unwrap().display_name.as_deref(), Some("login-user"));
+}
+
+#[tokio::test]
+async fn authorize_rejects_unknown_state() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+
+    let response = client.get("/authorize?code=forged-user&state=guessed").await;
+
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(response.location.as_deref(), Some("/"));
+    assert_eq!(mock.calls("forged-user", "/api/token"), 0);
+}
+
+#[tokio::test]
+async fn session_cookies_are_host_only_and_secure_over_https() {
+    common::setup();
+
+    // the header is ignored without a trusted proxy
+    let mut request = TestClient::server_fn_request::<Login>("");
+    request.headers_mut().insert("x-forwarded-proto", "https".parse().unwrap());
+    let direct = TestClient::new().request(request).await;
+
+    let [cookie] = &direct.set_cookies[..] else { panic!("{:?}", direct.set_cookies) };
+    assert!(!cookie.contains("Domain="), "{cookie}");
+    assert!(!cookie.contains("Secure"), "{cookie}");
+
+    let mut request = TestClient::server_fn_request::<Login>("");
+    request.headers_mut().insert("x-forwarded-proto", "https".parse().unwrap());
+    request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 50000))));
+    let proxied = TestClient::new().request(request).await;
+
+    let [cookie] = &proxied.set_cookies[..] else { panic!("{:?}", proxied.set_cookies) };
+    assert!(!cookie.contains("Domain="), "{cookie}");
+    assert!(cookie.ends_with("; Secure"), "{cookie}");
+}
+
+/// Start a login without finishing it, returning its state.
+async fn start_login(client: &mut TestClient) -> String {
+    let info = client.server_fn::<Login>("").await;
+    let info: serde_json::Value = serde_json::from_str(&info.body).unwrap();
+
+    common::query_param(info["url"].as_str().unwrap(), "state").unwrap()
+}
+
+#[tokio::test]
+async fn logins_started_in_two_tabs_can_both_be_finished() {
+    common::setup();
+    let mut client = TestClient::new();
+
+    let first_tab = start_login(&mut client).await;
+    let _second_tab = start_login(&mut client).await;
+
+    let response = client.get(&format!("/authorize?code=two-tab-user&state={first_tab}")).await;
+
+    assert_eq!(response.location.as_deref(), Some("/dashboard"));
+}
+
+#[tokio::test]
+async fn only_the_latest_logins_are_remembered() {
+    common::setup();
+    let mut client = TestClient::new();
+
+    let mut states = Vec::new();
+    for _ in 0..=MAX_PENDING_STATES {
+        states.push(start_login(&mut client).await);
+    }
+
+    let oldest = client.get(&format!("/authorize?code=many-tab-user&state={}", states[0])).await;
+    let second = client.get(&format!("/authorize?code=many-tab-user&state={}", states[1])).await;
+
+    assert_eq!(oldest.location.as_deref(), Some("/"));
+    assert_eq!(second.location.as_deref(), Some("/dashboard"));
+}
+
+#[tokio::test]
+async fn authorize_rejects_replayed_state() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+
+    let state = start_login(&mut client).await;
+
+    let first = client.get(&format!("/authorize?code=replay-user&state={state}")).await;
+    let second = client.get(&format!("/authorize?code=replay-user&state={state}")).await;
+
+    assert_eq!(first.location.as_deref(), Some("/dashboard"));
+    assert_eq!(second.location.as_deref(), Some("/"));
+    assert_eq!(mock.calls("replay-user", "/api/token"), 1);
+}
+
+#[tokio::test]
+async fn logging_out_takes_a_post() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("logout-user").await;
+
+    // a link or an image can't log anyone out
+    assert_eq!(client.get("/logout").await.status, StatusCode::METHOD_NOT_ALLOWED);
+    assert_ne!(client.server_fn::<GetCurrentUser>("").await.body, "null");
+
+    let response = client.request(Request::post("/logout").body(Body::empty()).unwrap()).await;
+
+    assert_eq!(response.status, StatusCode::SEE_OTHER);
+    assert_eq!(response.location.as_deref(), Some("/"));
+    assert_eq!(client.server_fn::<GetCurrentUser>("").await.body, "null");
+}
+
+#[tokio::test]
+async fn server_functions_need_a_login() {
+    common::setup();
+    let mut client = TestClient::new();
+
+    let me = client.server_fn::<GetCurrentUser>("").await;
+
+    assert_eq!(me.status, StatusCode::OK);
+    assert_eq!(me.body, "null");
+}
+
+#[tokio::test]
+async fn top_artists_are_cached() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("cache-user").await;
+
+    let first = client.server_fn::<GetTopArtists>("range=medium_term").await;
+    let second = client.server_fn::<GetTopArtists>("range=medium_term").await;
+
+    let artists: Option<Vec<FullArtist>> = serde_json::from_str(&first.body).unwrap();
+    let names: Vec<String> = artists.unwrap().into_iter().map(|artist| artist.name).collect();
+
+    assert_eq!(names, ["The Orbiters", "Nebula Drive", "Quiet Comet"]);
+    assert_eq!(first.body, second.body);
+    assert_eq!(mock.calls("cache-user", "/v1/me/top/artists"), 1);
+}
+
+#[tokio::test]
+async fn top_tracks_are_cached() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("tracks-user").await;
+
+    let first = client.server_fn::<GetTopTracks>("range=short_term").await;
+    let second = client.server_fn::<GetTopTracks>("range=short_term").await;
+
+    let tracks: Option<Vec<TopTrack>> = serde_json::from_str(&first.body).unwrap();
+    let tracks = tracks.unwrap();
+
+    assert_eq!(tracks.len(), 2);
+    assert_eq!(tracks[0].name, "Escape Velocity");
+    assert_eq!(tracks[0].artists.len(), 2);
+    assert_eq!(tracks[0].duration_ms, 215_000);
+    assert_eq!(tracks[1].album_image, None);
+    assert_eq!(first.body, second.body);
+    assert_eq!(mock.calls("tracks-user", "/v1/me/top/tracks"), 1);
+}
+
+#[tokio::test]
+async fn followed_artists_are_paged_through_and_cached() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("follow-user").await;
+
+    let first = client.server_fn::<GetFollowedArtists>("").await;
+    let second = client.server_fn::<GetFollowedArtists>("").await;
+
+    let followed: Option<Vec<FullArtist>> = serde_json::from_str(&first.body).unwrap();
+    let names: Vec<String> = followed.unwrap().into_iter().map(|artist| artist.name).collect();
+
+    assert_eq!(names, ["The Orbiters", "Distant Pulsar"]);
+    assert_eq!(first.body, second.body);
+    // one request per page, and none once cached
+    assert_eq!(mock.calls("follow-user", "/v1/me/following"), 2);
+}
+
+#[tokio::test]
+async fn constellation_links_related_genre_and_track_sharing_artists() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("constellation-user").await;
+
+    let response = client.server_fn::<GetConstellation>("range=short_term").await;
+    let constellation: Option<Constellation> = serde_json::from_str(&response.body).unwrap();
+    let constellation = constellation.unwrap();
+
+    let edges: Vec<(usize, usize, EdgeKind)> = constellation
+        .edges
+        .iter()
+        .map(|edge| (edge.source, edge.target, edge.kind))
+        .collect();
+
+    assert_eq!(constellation.nodes.len(), 3);
+    assert_eq!(
+        edges,
+        [
+            (0, 1, EdgeKind::Related),
+            (0, 1, EdgeKind::SharedGenre),
+            (0, 2, EdgeKind::SharedTrack),
+            (1, 2, EdgeKind::Related),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn genre_breakdown_covers_every_range() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("genre-user").await;
+
+    let response = client.server_fn::<GetGenreBreakdown>("").await;
+    let ranges: Option<Vec<RangeGenres>> = serde_json::from_str(&response.body).unwrap();
+    let ranges = ranges.unwrap();
+
+    assert_eq!(ranges.len(), 3);
+
+    let top = &ranges[0].genres[0];
+    assert_eq!(top.genre, "indie rock");
+    assert_eq!(top.artists, 2);
+    assert!((top.share - 5.0 / 6.0).abs() < 1e-6);
+}
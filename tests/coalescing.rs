@@ -0,0 +1,107 @@
+//! Every test here slows the mock down, so concurrent requests overlap.
+
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use futures::future::join_all;
+use rspotify::model::{FullArtist, PrivateUser};
+
+use common::{MockSpotify, TestClient};
+use starify::{
+    client::{GetCurrentUser, GetTopArtists},
+    constellation::GetConstellation,
+};
+
+async fn setup(user: &str) -> (&'static MockSpotify, TestClient) {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login(user).await;
+
+    mock.set_latency(Duration::from_millis(200));
+
+    (mock, client)
+}
+
+#[tokio::test]
+async fn concurrent_misses_share_one_fetch() {
+    let (mock, client) = setup("coalesced-user").await;
+    let logins = mock.calls("coalesced-user", "/v1/me");
+
+    let users = join_all((0..4).map(|_| {
+        let mut client = client.clone();
+        async move { client.server_fn::<GetCurrentUser>("").await }
+    }));
+
+    let artists = join_all((0..4).map(|_| {
+        let mut client = client.clone();
+        async move { client.server_fn::<GetTopArtists>("range=short_term").await }
+    }));
+
+    let (users, artists) = tokio::join!(users, artists);
+
+    for response in users {
+        let me: Option<PrivateUser> = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(me.map(|me| me.id.to_string()), Some("spotify:user:coalesced-user".to_string()));
+    }
+
+    for response in artists {
+        let artists: Option<Vec<FullArtist>> = serde_json::from_str(&response.body).unwrap();
+        assert_eq!(artists.map(|artists| artists.len()), Some(3));
+    }
+
+    assert_eq!(mock.calls("coalesced-user", "/v1/me") - logins, 1);
+    assert_eq!(mock.calls("coalesced-user", "/v1/me/top/artists"), 1);
+}
+
+#[tokio::test]
+async fn concurrent_misses_share_a_failed_fetch() {
+    let (mock, client) = setup("unavailable-user").await;
+
+    let responses = join_all((0..4).map(|_| {
+        let mut client = client.clone();
+        async move { client.server_fn::<GetTopArtists>("range=short_term").await }
+    }))
+    .await;
+
+    assert!(responses.iter().all(|response| response.status == StatusCode::INTERNAL_SERVER_ERROR));
+    assert_eq!(mock.calls("unavailable-user", "/v1/me/top/artists"), 1);
+
+    // the failure isn't kept, later requests try again
+    client.clone().server_fn::<GetTopArtists>("range=short_term").await;
+    assert_eq!(mock.calls("unavailable-user", "/v1/me/top/artists"), 2);
+}
+
+#[tokio::test]
+async fn different_keys_are_fetched_separately() {
+    let (mock, client) = setup("uncoalesced-user").await;
+
+    let ranges = join_all(["short_term", "medium_term", "long_term"].map(|range| {
+        let mut client = client.clone();
+        async move { client.server_fn::<GetTopArtists>(&format!("range={range}")).await }
+    }))
+    .await;
+
+    assert!(ranges.iter().all(|response| response.body.starts_with('[')));
+    assert_eq!(mock.calls("uncoalesced-user", "/v1/me/top/artists"), 3);
+}
+
+#[tokio::test]
+async fn constellations_share_their_top_artists() {
+    let (mock, client) = setup("constellation-coalesced-user").await;
+
+    let constellations = join_all((0..3).map(|_| {
+        let mut client = client.clone();
+        async move { client.server_fn::<GetConstellation>("range=medium_term").await }
+    }))
+    .await;
+
+    let bodies: Vec<&str> = constellations.iter().map(|response| response.body.as_str()).collect();
+    assert!(bodies.iter().all(|body| *body == bodies[0]));
+
+    assert_eq!(mock.calls("constellation-coalesced-user", "/v1/me/top/artists"), 1);
+    assert_eq!(mock.calls("constellation-coalesced-user", "/v1/me/top/tracks"), 1);
+    // one request per top artist, not per constellation
+    assert_eq!(mock.calls("constellation-coalesced-user", "/v1/artists/related-artists"), 3);
+}
@@ -0,0 +1,350 @@
+//! A local stand-in for `accounts.spotify.com` and `api.spotify.com`.
+//!
+//! Every authorization code is accepted and names the user it logs in as:
+//! the code `alice` is exchanged for the access token `access-alice` and the
+//! refresh token `refresh-alice`, and `/v1/me` answers with the user `alice`.
+//! Refresh tokens for users whose name starts with `revoked` are rejected as
+//! `invalid_grant`. Refreshing for users whose name starts with `ratelimited`
+//! fails with a rate limit, and for `misconfigured` users as if the client
+//! credentials were wrong. The top artists of users whose name starts with
+//! `unavailable` can't be fetched.
+//!
+//! Token requests must authenticate the client, either with the client
+//! secret or as a PKCE client with a client ID and, for new logins, a code verifier.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use serde_json::{json, Value};
+
+const ARTISTS: &str = include_str!("../fixtures/artists.json");
+const TRACKS: &str = include_str!("../fixtures/tracks.json");
+const FOLLOWED_ARTISTS: &str = include_str!("../fixtures/followed_artists.json");
+const RELATED_ARTISTS: &str = include_str!("../fixtures/related_artists.json");
+
+#[derive(Clone)]
+pub struct MockSpotify {
+    pub addr: SocketAddr,
+    state: MockState,
+}
+
+#[derive(Clone, Default)]
+struct MockState {
+    /// Number of requests per user and endpoint.
+    calls: Arc<Mutex<HashMap<(String, &'static str), usize>>>,
+    /// Extra time every response takes.
+    latency: Arc<Mutex<Duration>>,
+    /// The PKCE code verifier of each user's latest login.
+    verifiers: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl MockState {
+    async fn record(&self, user: &str, endpoint: &'static str) {
+        *self
+            .calls
+            .lock()
+            .unwrap()
+            .entry((user.to_string(), endpoint))
+            .or_default() += 1;
+
+        let latency = *self.latency.lock().unwrap();
+        tokio::time::sleep(latency).await;
+    }
+}
+
+impl MockSpotify {
+    /// Start the server on its own thread, so it outlives the runtime of any single test.
+    pub fn start() -> Self {
+        let state = MockState::default();
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        let app = Router::new()
+            .route("/api/token", post(token))
+            // rspotify asks for `me/`
+            .route("/v1/me/", get(me))
+            .route("/v1/me/top/artists", get(top_artists))
+            .route("/v1/me/top/tracks", get(top_tracks))
+            .route("/v1/me/following", get(followed_artists))
+            .route("/v1/artists/:id/related-artists", get(related_artists))
+            .with_state(state.clone());
+
+        std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async move {
+                    let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap())
+                        .serve(app.into_make_service());
+
+                    sender.send(server.local_addr()).unwrap();
+                    server.await.unwrap();
+                });
+        });
+
+        Self {
+            addr: receiver.recv().unwrap(),
+            state,
+        }
+    }
+
+    /// Base URL to use as `rspotify::Config::api_base_url`.
+    pub fn api_base_url(&self) -> String {
+        format!("http://{}/v1/", self.addr)
+    }
+
+    /// Base URL to use as `rspotify::Config::auth_base_url`.
+    pub fn auth_base_url(&self) -> String {
+        format!("http://{}/", self.addr)
+    }
+
+    /// How many times `user` called `endpoint`, e.g. `"/v1/me"`.
+    pub fn calls(&self, user: &str, endpoint: &'static str) -> usize {
+        self.state
+            .calls
+            .lock()
+            .unwrap()
+            .get(&(user.to_string(), endpoint))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The PKCE code verifier `user` last logged in with.
+    pub fn verifier(&self, user: &str) -> Option<String> {
+        self.state.verifiers.lock().unwrap().get(user).cloned()
+    }
+
+    /// Delay every response by `latency`.
+    pub fn set_latency(&self, latency: Duration) {
+        *self.state.latency.lock().unwrap() = latency;
+    }
+}
+
+fn artists() -> Vec<Value> {
+    serde_json::from_str(ARTISTS).unwrap()
+}
+
+/// The user named by a `Bearer access-{user}` authorization header.
+fn bearer_user(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer access-"))
+        .map(str::to_string)
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        Json(json!({ "error": { "status": 401, "message": "No token provided" } })),
+    )
+        .into_response()
+}
+
+async fn token(
+    State(state): State<MockState>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let confidential = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("Basic "));
+    let pkce = !confidential && form.contains_key("client_id");
+
+    if !confidential && !pkce {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "invalid_client" }))).into_response();
+    }
+
+    let refreshing = form
+        .get("refresh_token")
+        .and_then(|token| token.strip_prefix("refresh-"))
+        .filter(|_| form.get("grant_type").is_some_and(|grant| grant == "refresh_token"));
+
+    if refreshing.is_some_and(|user| user.starts_with("ratelimited")) {
+        return (StatusCode::TOO_MANY_REQUESTS, [("retry-after", "30")]).into_response();
+    }
+
+    if refreshing.is_some_and(|user| user.starts_with("misconfigured")) {
+        return (StatusCode::UNAUTHORIZED, Json(json!({ "error": "invalid_client" }))).into_response();
+    }
+
+    let user = match form.get("grant_type").map(String::as_str) {
+        Some("authorization_code") if pkce => {
+            let (Some(user), Some(verifier)) = (form.get("code"), form.get("code_verifier")) else {
+                return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_request" }))).into_response();
+            };
+
+            state.verifiers.lock().unwrap().insert(user.clone(), verifier.clone());
+
+            Some(user.clone())
+        }
+        Some("authorization_code") => form.get("code").cloned(),
+        Some("refresh_token") => form
+            .get("refresh_token")
+            .and_then(|token| token.strip_prefix("refresh-"))
+            .filter(|user| !user.starts_with("revoked"))
+            .map(str::to_string),
+        _ => None,
+    };
+
+    let Some(user) = user else {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": "invalid_grant" }))).into_response();
+    };
+
+    state.record(&user, "/api/token").await;
+
+    Json(json!({
+        "access_token": format!("access-{user}"),
+        "token_type": "Bearer",
+        "expires_in": 3600,
+        "refresh_token": format!("refresh-{user}"),
+        "scope": "user-top-read user-follow-read",
+    }))
+    .into_response()
+}
+
+async fn me(State(state): State<MockState>, headers: HeaderMap) -> Response {
+    let Some(user) = bearer_user(&headers) else {
+        return unauthorized();
+    };
+
+    state.record(&user, "/v1/me").await;
+
+    Json(json!({
+        "display_name": user,
+        "external_urls": { "spotify": format!("https://open.spotify.com/user/{user}") },
+        "followers": { "href": null, "total": 3 },
+        "href": format!("https://api.spotify.com/v1/users/{user}"),
+        "id": user,
+        "images": [{ "height": 64, "url": format!("https://i.scdn.co/image/{user}"), "width": 64 }],
+        "type": "user",
+        "uri": format!("spotify:user:{user}"),
+    }))
+    .into_response()
+}
+
+async fn top_artists(State(state): State<MockState>, headers: HeaderMap) -> Response {
+    let Some(user) = bearer_user(&headers) else {
+        return unauthorized();
+    };
+
+    state.record(&user, "/v1/me/top/artists").await;
+
+    if user.starts_with("unavailable") {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "error": { "status": 503, "message": "Service unavailable" } })),
+        )
+            .into_response();
+    }
+
+    let items = artists();
+
+    Json(json!({
+        "href": "https://api.spotify.com/v1/me/top/artists",
+        "limit": 50,
+        "next": null,
+        "offset": 0,
+        "previous": null,
+        "total": items.len(),
+        "items": items,
+    }))
+    .into_response()
+}
+
+async fn top_tracks(State(state): State<MockState>, headers: HeaderMap) -> Response {
+    let Some(user) = bearer_user(&headers) else {
+        return unauthorized();
+    };
+
+    state.record(&user, "/v1/me/top/tracks").await;
+
+    let items: Vec<Value> = serde_json::from_str(TRACKS).unwrap();
+
+    Json(json!({
+        "href": "https://api.spotify.com/v1/me/top/tracks",
+        "limit": 50,
+        "next": null,
+        "offset": 0,
+        "previous": null,
+        "total": items.len(),
+        "items": items,
+    }))
+    .into_response()
+}
+
+/// Serves one artist per page, so clients have to follow the cursors.
+async fn followed_artists(
+    State(state): State<MockState>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let Some(user) = bearer_user(&headers) else {
+        return unauthorized();
+    };
+
+    state.record(&user, "/v1/me/following").await;
+
+    let followed: Vec<Value> = serde_json::from_str(FOLLOWED_ARTISTS).unwrap();
+
+    let start = match query.get("after") {
+        Some(after) => followed
+            .iter()
+            .position(|artist| artist["id"] == after.as_str())
+            .map_or(followed.len(), |position| position + 1),
+        None => 0,
+    };
+
+    let items: Vec<Value> = followed.iter().skip(start).take(1).cloned().collect();
+    let after = items.last().map(|artist| artist["id"].clone());
+    let next = after
+        .as_ref()
+        .filter(|_| start + 1 < followed.len())
+        .map(|after| format!("https://api.spotify.com/v1/me/following?type=artist&after={}", after.as_str().unwrap()));
+
+    Json(json!({
+        "artists": {
+            "href": "https://api.spotify.com/v1/me/following?type=artist",
+            "limit": 1,
+            "next": next,
+            "cursors": { "after": after },
+            "total": followed.len(),
+            "items": items,
+        }
+    }))
+    .into_response()
+}
+
+async fn related_artists(
+    State(state): State<MockState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let Some(user) = bearer_user(&headers) else {
+        return unauthorized();
+    };
+
+    state.record(&user, "/v1/artists/related-artists").await;
+
+    let related: HashMap<String, Vec<String>> = serde_json::from_str(RELATED_ARTISTS).unwrap();
+    let ids = related.get(&id).cloned().unwrap_or_default();
+
+    let artists: Vec<Value> = artists()
+        .into_iter()
+        .filter(|artist| ids.iter().any(|id| artist["id"] == id.as_str()))
+        .collect();
+
+    Json(json!({ "artists": artists })).into_response()
+}
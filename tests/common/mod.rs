@@ -0,0 +1,212 @@
+//! Shared setup for integration tests: one mock Spotify server, one
+//! configuration and one in-memory store per test binary, and a small client
+//! for the app router.
+
+#![allow(dead_code)]
+
+pub mod mock_spotify;
+
+use std::sync::{Arc, OnceLock};
+
+use axum::{
+    body::Body,
+    http::{header, Request, StatusCode},
+    Router,
+};
+use leptos::server_fn::ServerFn;
+use time::Duration;
+use tower::ServiceExt;
+
+use starify::{
+    config::{self, AuthFlow, CacheConfig, Config, SpotifyConfig, TokenKey},
+    server,
+    store::{MemoryStore, SharedStore},
+};
+
+pub use mock_spotify::MockSpotify;
+
+/// Start the mock server and initialize the configuration, once per test binary.
+pub fn setup() -> &'static MockSpotify {
+    setup_with_flow(AuthFlow::AuthCode)
+}
+
+/// [`setup`] with users logging in through `auth_flow`. Every test of a binary must use the same flow.
+pub fn setup_with_flow(auth_flow: AuthFlow) -> &'static MockSpotify {
+    static MOCK: OnceLock<MockSpotify> = OnceLock::new();
+
+    MOCK.get_or_init(|| {
+        let mock = MockSpotify::start();
+
+        config::init(Config {
+            spotify: SpotifyConfig {
+                client_id: "client-id".to_string(),
+                client_secret: Some("client-secret".to_string()),
+                auth_flow,
+                api_base_url: mock.api_base_url(),
+                auth_base_url: mock.auth_base_url(),
+            },
+            bind_address: "127.0.0.1:3000".parse().unwrap(),
+            public_url: "http://localhost:3000".to_string(),
+            // only requests given a `ConnectInfo` come from a peer at all
+            trusted_proxies: vec![[127, 0, 0, 1].into()],
+            cookie_domain: None,
+            // never opened, everything is kept in `store()`
+            database_path: std::env::temp_dir().join("starify-test"),
+            state_secret: Some(b"a test secret that is long enough".to_vec()),
+            token_keys: vec![current_token_key(), old_token_key()],
+            session_expiry: Duration::days(1),
+            cache: CacheConfig {
+                userinfo_ttl: Duration::days(1),
+                short_term_ttl: Duration::hours(6),
+                medium_term_ttl: Duration::days(1),
+                long_term_ttl: Duration::weeks(1),
+                refresh_cooldown: Duration::minutes(10),
+            },
+        });
+
+        mock
+    })
+}
+
+/// The store every [`TestClient`] of a test binary shares.
+pub fn store() -> SharedStore {
+    static STORE: OnceLock<SharedStore> = OnceLock::new();
+
+    STORE.get_or_init(|| Arc::new(MemoryStore::new())).clone()
+}
+
+/// The key new tokens are encrypted with.
+pub fn current_token_key() -> TokenKey {
+    TokenKey { id: "test-2".to_string(), key: [2; 32] }
+}
+
+/// A rotated key that tokens can still be decrypted with.
+pub fn old_token_key() -> TokenKey {
+    TokenKey { id: "test-1".to_string(), key: [1; 32] }
+}
+
+/// The app router with its cookies, like a browser.
+///
+/// Clones share the router and start with the same cookies, like tabs.
+#[derive(Clone)]
+pub struct TestClient {
+    router: Router,
+    cookies: Vec<String>,
+}
+
+/// A response with its body read.
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub location: Option<String>,
+    pub set_cookies: Vec<String>,
+    pub body: String,
+    pub bytes: Vec<u8>,
+}
+
+impl TestClient {
+    pub fn new() -> Self {
+        let config = config::get();
+        let leptos_options = leptos::LeptosOptions::builder()
+            .output_name("starify")
+            .build();
+
+        Self {
+            router: server::router(
+                config,
+                leptos_options,
+                store(),
+                || async { StatusCode::NOT_FOUND },
+            ),
+            cookies: Vec::new(),
+        }
+    }
+
+    pub async fn request(&mut self, request: Request<Body>) -> TestResponse {
+        let (mut parts, body) = request.into_parts();
+
+        if !self.cookies.is_empty() {
+            parts
+                .headers
+                .insert(header::COOKIE, self.cookies.join("; ").parse().unwrap());
+        }
+
+        let response = self
+            .router
+            .clone()
+            .oneshot(Request::from_parts(parts, body))
+            .await
+            .unwrap();
+
+        let set_cookies: Vec<String> = response
+            .headers()
+            .get_all(header::SET_COOKIE)
+            .iter()
+            .map(|cookie| cookie.to_str().unwrap().to_string())
+            .collect();
+
+        for cookie in &set_cookies {
+            let pair = cookie.split(';').next().unwrap().to_string();
+            let name = pair.split('=').next().unwrap().to_string();
+
+            self.cookies.retain(|cookie| !cookie.starts_with(&format!("{name}=")));
+
+            // removed cookies are sent back already expired
+            if !cookie.contains("Max-Age=0") {
+                self.cookies.push(pair);
+            }
+        }
+
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .map(|location| location.to_str().unwrap().to_string());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        TestResponse {
+            status,
+            location,
+            set_cookies,
+            body: String::from_utf8_lossy(&body).into_owned(),
+            bytes: body.to_vec(),
+        }
+    }
+
+    pub async fn get(&mut self, uri: &str) -> TestResponse {
+        self.request(Request::get(uri).body(Body::empty()).unwrap()).await
+    }
+
+    /// A request calling the server function `F` with url-encoded `args`.
+    pub fn server_fn_request<F: ServerFn<()>>(args: &str) -> Request<Body> {
+        Request::post(format!("{}/{}", F::prefix(), F::url()))
+            .header(header::ACCEPT, "application/json")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(args.to_string()))
+            .unwrap()
+    }
+
+    /// Call the server function `F` with url-encoded `args`, returning its JSON output.
+    pub async fn server_fn<F: ServerFn<()>>(&mut self, args: &str) -> TestResponse {
+        self.request(Self::server_fn_request::<F>(args)).await
+    }
+
+    /// Log in through the mock Spotify server as `user`.
+    pub async fn login(&mut self, user: &str) -> TestResponse {
+        let info = self.server_fn::<starify::app::Login>("").await;
+        assert_eq!(info.status, StatusCode::OK, "{}", info.body);
+
+        let info: serde_json::Value = serde_json::from_str(&info.body).unwrap();
+        let state = query_param(info["url"].as_str().unwrap(), "state").unwrap();
+
+        self.get(&format!("/authorize?code={user}&state={state}")).await
+    }
+}
+
+/// The value of `name` in the query of `url`, without decoding.
+pub fn query_param(url: &str, name: &str) -> Option<String> {
+    url.split_once('?')?
+        .1
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(&format!("{name}=")))
+        .map(str::to_string)
+}
@@ -0,0 +1,63 @@
+//! The environment is global, so tests take turns through [`ENV`].
+
+use std::{env, sync::Mutex};
+
+use starify::config::{Config, CONFIG_PATH_VAR};
+
+static ENV: Mutex<()> = Mutex::new(());
+
+const SPOTIFY: &str = "[spotify]\nclient_id = \"client-id\"\nclient_secret = \"client-secret\"\n";
+
+/// Load `file` as the configuration file with `vars` set, returning the error.
+fn load_err(name: &str, file: &str, vars: &[(&str, &str)]) -> String {
+    let _env = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    let path = env::temp_dir().join(format!("starify-config-test-{name}-{}.toml", std::process::id()));
+    std::fs::write(&path, file).unwrap();
+
+    env::set_var(CONFIG_PATH_VAR, &path);
+    for (var, value) in vars {
+        env::set_var(var, value);
+    }
+
+    let result = Config::load("127.0.0.1:3000".parse().unwrap());
+
+    for (var, _) in vars {
+        env::remove_var(var);
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    result.unwrap_err().to_string()
+}
+
+#[test]
+fn unparsable_env_vars_are_reported_with_their_value() {
+    let err = load_err("unparsable", SPOTIFY, &[("STARIFY_LONG_TERM_TTL_SECS", "a week")]);
+
+    assert!(err.contains(r#"STARIFY_LONG_TERM_TTL_SECS="a week" is invalid"#), "{err}");
+    assert!(!err.contains("cache.long_term_ttl_secs"), "{err}");
+}
+
+#[test]
+fn missing_required_settings_are_reported() {
+    let err = load_err("missing", "[spotify]\nclient_secret = \"client-secret\"\n", &[]);
+
+    assert!(err.contains("`spotify.client_id` is required (or set SPOTIFY_CLIENT_ID)"), "{err}");
+}
+
+#[test]
+fn durations_must_be_positive() {
+    let file = format!("session_expiry_secs = 0\n{SPOTIFY}");
+    let err = load_err("non-positive", &file, &[("STARIFY_SHORT_TERM_TTL_SECS", "-60")]);
+
+    assert!(err.contains("`session_expiry_secs` is invalid: must be a positive number of seconds"), "{err}");
+    assert!(err.contains("`cache.short_term_ttl_secs` is invalid: must be a positive number of seconds"), "{err}");
+}
+
+#[test]
+fn durations_must_not_overflow() {
+    let file = format!("session_expiry_secs = {}\n{SPOTIFY}", i64::MAX);
+    let err = load_err("out-of-range", &file, &[]);
+
+    assert!(err.contains("`session_expiry_secs` is invalid: must be at most"), "{err}");
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use axum::http::StatusCode;
+use resvg::tiny_skia::Pixmap;
+
+use starify::{
+    constellation::layout::Point,
+    export::{
+        data::{DataExport, FollowedArtist, LayoutSize, Profile, Table, SCHEMA_VERSION},
+        png,
+        scene::{Color, Scene, Shape},
+    },
+};
+
+use common::TestClient;
+
+#[tokio::test]
+async fn exports_need_a_login() {
+    common::setup();
+    let mut client = TestClient::new();
+
+    let response = client.get("/export/constellation.svg").await;
+
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn exports_reject_unknown_ranges() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("range-export-user").await;
+
+    let response = client.get("/export/constellation.svg?range=forever").await;
+
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn svg_export_has_the_caption_and_every_star() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("svg-user").await;
+
+    let response = client.get("/export/constellation.svg?range=long").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.starts_with("<svg"));
+    assert!(response.body.contains(">svg-user</text>"));
+    assert!(response.body.contains(">All Time</text>"));
+    assert!(response.body.contains(">1. The Orbiters</text>"));
+    // the genre region, three stars and the ring of the one that's followed
+    assert_eq!(response.body.matches("<circle").count(), 5);
+    assert_eq!(response.body.matches(r##"fill="none" stroke="#ffffff" stroke-opacity="0.600""##).count(), 1);
+}
+
+#[tokio::test]
+async fn png_export_is_a_social_media_sized_png() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("png-user").await;
+
+    let response = client.get("/export/constellation.png").await;
+
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(&response.bytes[..8], b"\x89PNG\r\n\x1a\n");
+    assert_eq!(&response.bytes[12..16], b"IHDR");
+    assert_eq!(u32::from_be_bytes(response.bytes[16..20].try_into().unwrap()), 1200);
+    assert_eq!(u32::from_be_bytes(response.bytes[20..24].try_into().unwrap()), 630);
+}
+
+/// A PNG with nothing but `text` on it, decoded.
+fn text_png(text: &str) -> Pixmap {
+    let scene = Scene {
+        background: Color::BACKGROUND,
+        shapes: vec![Shape::Text {
+            position: Point { x: 20.0, y: 20.0 },
+            size: 48.0,
+            text: text.to_string(),
+            color: Color::WHITE,
+        }],
+    };
+
+    Pixmap::decode_png(&png::render(&scene).unwrap()).unwrap()
+}
+
+#[test]
+fn png_text_keeps_accents() {
+    let accented = text_png("Björk");
+
+    assert_ne!(accented.data(), text_png("Bjork").data());
+    assert_ne!(accented.data(), text_png("Bj?rk").data());
+    assert_ne!(accented.data(), text_png("").data());
+}
+
+#[tokio::test]
+async fn data_export_needs_a_login() {
+    common::setup();
+    let mut client = TestClient::new();
+
+    assert_eq!(client.get("/export/data.json").await.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(client.get("/export/artists.csv").await.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn json_export_has_every_range_with_coordinates() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("json-user").await;
+
+    let response = client.get("/export").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let data: DataExport = serde_json::from_str(&response.body).unwrap();
+
+    assert_eq!(data.schema_version, SCHEMA_VERSION);
+    assert_eq!(data.profile.id, "spotify:user:json-user");
+    assert_eq!(
+        data.ranges.iter().map(|range| range.range.as_str()).collect::<Vec<_>>(),
+        ["short", "medium", "long"]
+    );
+
+    for range in &data.ranges {
+        assert_eq!(range.artists.len(), 3);
+        assert_eq!(range.artists[0].rank, 1);
+        assert_eq!(range.artists[0].name, "The Orbiters");
+        assert!(range.artists.iter().all(|artist| {
+            (0.0..=data.layout.width).contains(&artist.x) && (0.0..=data.layout.height).contains(&artist.y)
+        }));
+
+        let ids: Vec<&str> = range.artists.iter().map(|artist| artist.id.as_str()).collect();
+        assert!(!range.edges.is_empty());
+        assert!(range.edges.iter().all(|edge| ids.contains(&edge.source.as_str()) && ids.contains(&edge.target.as_str())));
+        assert!(!range.genres.is_empty());
+
+        assert_eq!(
+            range.tracks.iter().map(|track| (track.rank, track.name.as_str())).collect::<Vec<_>>(),
+            [(1, "Escape Velocity"), (2, "Redshift")]
+        );
+        assert_eq!(range.tracks[0].artists, ["The Orbiters", "Quiet Comet"]);
+    }
+
+    assert_eq!(
+        data.followed.iter().map(|artist| (artist.name.as_str(), artist.followers)).collect::<Vec<_>>(),
+        [("The Orbiters", 1200), ("Distant Pulsar", 90)]
+    );
+}
+
+#[tokio::test]
+async fn csv_export_has_one_row_per_artist_and_range() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("csv-user").await;
+
+    let response = client.get("/export/artists.csv").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let lines: Vec<&str> = response.body.lines().collect();
+    assert_eq!(lines[0], "range,rank,id,name,genres,popularity,image,cluster,x,y");
+    assert_eq!(lines.len(), 1 + 3 * 3);
+    assert!(lines[1].starts_with("short,1,"));
+
+    let profile = client.get("/export/profile.csv").await;
+    assert_eq!(profile.status, StatusCode::OK);
+    assert!(profile.body.lines().nth(1).unwrap().starts_with(&format!("{SCHEMA_VERSION},")));
+
+    let tracks = client.get("/export/tracks.csv").await;
+    assert_eq!(tracks.status, StatusCode::OK);
+    assert_eq!(tracks.body.lines().nth(1).unwrap(), "short,1,spotify:track:00000000000000000000t1,Escape Velocity,The Orbiters;Quiet Comet,Low Orbit,215000");
+
+    let followed = client.get("/export/followed.csv").await;
+    assert_eq!(followed.status, StatusCode::OK);
+    assert_eq!(followed.body.lines().count(), 1 + 2);
+
+    assert_eq!(client.get("/export/playlists.csv").await.status, StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn csv_cells_are_not_read_as_formulas() {
+    let artist = |name: &str| FollowedArtist {
+        id: "spotify:artist:0000000000000000000001".to_string(),
+        name: name.to_string(),
+        genres: vec!["-core".to_string()],
+        popularity: 50,
+        followers: 10,
+        image: None,
+    };
+
+    let data = DataExport {
+        schema_version: SCHEMA_VERSION,
+        exported_at: 0,
+        profile: Profile {
+            id: "spotify:user:csv-user".to_string(),
+            display_name: Some("@csv".to_string()),
+            followers: None,
+            image: None,
+        },
+        layout: LayoutSize { width: 1.0, height: 1.0 },
+        ranges: Vec::new(),
+        followed: vec![artist("=HYPERLINK(\"https://example.com\")"), artist("+1"), artist("Plain")],
+    };
+
+    let followed = data.csv(Table::Followed);
+    let rows: Vec<&str> = followed.lines().skip(1).collect();
+    assert_eq!(rows, [
+        r#"spotify:artist:0000000000000000000001,"'=HYPERLINK(""https://example.com"")",'-core,50,10,"#,
+        "spotify:artist:0000000000000000000001,'+1,'-core,50,10,",
+        "spotify:artist:0000000000000000000001,Plain,'-core,50,10,",
+    ]);
+
+    assert!(data.csv(Table::Profile).contains(",'@csv,"));
+}
@@ -0,0 +1,225 @@
+mod common;
+
+use std::sync::Arc;
+
+use rspotify::model::TimeRange;
+use time::{Duration, OffsetDateTime};
+
+use axum::http::StatusCode;
+use rspotify::Token;
+
+use common::TestClient;
+use starify::{
+    auth::{tokens, Backend},
+    client::GetTopArtists,
+    config,
+    history::{self, GetRankMovement, HistoryArtist, HistorySnapshot, Movement, RankMovement},
+    server,
+    store::{MemoryStore, SharedStore, Store, DEFAULT_TREE},
+};
+
+fn artist(number: u8, name: &str) -> HistoryArtist {
+    HistoryArtist {
+        id: format!("spotify:artist:00000000000000000000{number:02}"),
+        name: name.to_string(),
+        image: None,
+    }
+}
+
+fn days_ago(days: i64) -> String {
+    (OffsetDateTime::now_utc().date() - Duration::days(days)).to_string()
+}
+
+fn put_snapshot(store: &dyn Store, user_id: &str, range: TimeRange, days: i64, artists: Vec<HistoryArtist>) {
+    history::put_snapshot(store, user_id, range, &HistorySnapshot {
+        date: days_ago(days),
+        taken_at: 0,
+        artists,
+    })
+    .unwrap();
+}
+
+fn backend() -> Backend {
+    let config = config::get();
+
+    server::backend(server::spotify_client(config), config, common::store())
+}
+
+/// A backend with a store of its own holding only the tokens of `user_ids`,
+/// so scheduled snapshots don't fetch for the users of other tests.
+fn isolated_backend(user_ids: &[&str]) -> Backend {
+    let config = config::get();
+    let store: SharedStore = Arc::new(MemoryStore::new());
+
+    for user_id in user_ids {
+        let token = tokens::get_token(&*common::store(), user_id).unwrap().expect("stored token");
+        tokens::put_token(&*store, user_id, &token).unwrap();
+    }
+
+    server::backend(server::spotify_client(config), config, store)
+}
+
+/// Make the stored token of `user_id` look expired, so it's refreshed before use.
+fn expire_token(user_id: &str) {
+    let mut token: Token = tokens::get_token(&*common::store(), user_id).unwrap().expect("stored token");
+
+    token.expires_at = Some(chrono::Utc::now() - chrono::Duration::minutes(1));
+
+    tokens::put_token(&*common::store(), user_id, &token).unwrap();
+}
+
+async fn rank_movement(client: &mut TestClient, args: &str) -> RankMovement {
+    let response = client.server_fn::<GetRankMovement>(args).await;
+    let movement: Option<RankMovement> = serde_json::from_str(&response.body).unwrap();
+
+    movement.unwrap()
+}
+
+#[tokio::test]
+async fn history_starts_with_the_first_visit() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("new-history-user").await;
+
+    let movement = rank_movement(&mut client, "range=short_term&days=7").await;
+
+    assert_eq!(movement.since, None);
+    assert_eq!(movement.until, days_ago(0));
+    assert_eq!(history::snapshots(&*common::store(), "spotify:user:new-history-user", TimeRange::ShortTerm).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn rank_movement_compares_with_the_snapshot_from_before_the_period() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("history-user").await;
+
+    let user_id = "spotify:user:history-user";
+    put_snapshot(&*common::store(), user_id, TimeRange::MediumTerm, 40, vec![artist(1, "The Orbiters")]);
+    put_snapshot(&*common::store(), user_id, TimeRange::MediumTerm, 10, vec![
+        artist(2, "Nebula Drive"),
+        artist(1, "The Orbiters"),
+        artist(9, "Faded Star"),
+    ]);
+    put_snapshot(&*common::store(), user_id, TimeRange::MediumTerm, 3, vec![artist(1, "The Orbiters")]);
+
+    let movement = rank_movement(&mut client, "range=medium_term&days=7").await;
+
+    assert_eq!(movement.since, Some(days_ago(10)));
+
+    let changes: Vec<(&str, Movement)> = movement
+        .changes
+        .iter()
+        .map(|change| (change.artist.name.as_str(), change.movement()))
+        .collect();
+
+    assert_eq!(changes, [
+        ("The Orbiters", Movement::Rising(1)),
+        ("Nebula Drive", Movement::Falling(1)),
+        ("Quiet Comet", Movement::New),
+        ("Faded Star", Movement::Dropped),
+    ]);
+}
+
+#[tokio::test]
+async fn scheduled_snapshots_cover_users_who_did_not_visit() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("absent-user").await;
+    client.login("historyless-user").await;
+
+    let absent = "spotify:user:absent-user";
+    let historyless = "spotify:user:historyless-user";
+    let logged_out = "spotify:user:logged-out-user";
+    let backend = isolated_backend(&[absent, historyless]);
+    put_snapshot(backend.store(), absent, TimeRange::LongTerm, 1, vec![artist(1, "The Orbiters")]);
+    put_snapshot(backend.store(), logged_out, TimeRange::LongTerm, 1, vec![artist(1, "The Orbiters")]);
+
+    assert_eq!(history::take_snapshots(&backend).await.unwrap(), 2);
+
+    for user_id in [absent, historyless] {
+        for range in [TimeRange::ShortTerm, TimeRange::MediumTerm, TimeRange::LongTerm] {
+            let snapshots = history::snapshots(backend.store(), user_id, range).unwrap();
+            assert_eq!(snapshots.last().unwrap().date, days_ago(0), "{user_id}");
+        }
+    }
+    assert_eq!(mock.calls("absent-user", "/v1/me/top/artists"), 3);
+    assert_eq!(mock.calls("historyless-user", "/v1/me/top/artists"), 3);
+
+    // without a stored token there's nobody to fetch for
+    let snapshots = history::snapshots(backend.store(), logged_out, TimeRange::LongTerm).unwrap();
+    assert_eq!(snapshots.len(), 1);
+
+    // and once everyone has today's snapshots, there's nothing left to take
+    assert_eq!(history::take_snapshots(&backend).await.unwrap(), 0);
+    assert_eq!(mock.calls("absent-user", "/v1/me/top/artists"), 3);
+}
+
+#[tokio::test]
+async fn scheduled_snapshots_skip_users_whose_refresh_token_is_revoked() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("revoked-scheduled-user").await;
+
+    let user_id = "spotify:user:revoked-scheduled-user";
+    expire_token(user_id);
+    let backend = isolated_backend(&[user_id]);
+
+    assert_eq!(history::take_snapshots(&backend).await.unwrap(), 0);
+
+    // the token is forgotten, so later runs don't try to refresh it again
+    assert_eq!(backend.store().get(DEFAULT_TREE, user_id).unwrap(), None);
+    assert_eq!(mock.calls("revoked-scheduled-user", "/v1/me/top/artists"), 0);
+}
+
+#[tokio::test]
+async fn cached_artists_are_not_recorded_as_todays_snapshot() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("cached-history-user").await;
+
+    let user_id = "spotify:user:cached-history-user";
+    client.server_fn::<GetTopArtists>("range=long_term").await;
+    history::delete_user_history(&*common::store(), user_id).unwrap();
+    put_snapshot(&*common::store(), user_id, TimeRange::LongTerm, 1, vec![artist(1, "The Orbiters")]);
+
+    // served from the cache, which can't say what today's top artists are
+    client.server_fn::<GetTopArtists>("range=long_term").await;
+    let snapshots = history::snapshots(&*common::store(), user_id, TimeRange::LongTerm).unwrap();
+    assert_eq!(snapshots.last().unwrap().date, days_ago(1));
+
+    // so the scheduled snapshot asks Spotify
+    assert!(history::take_user_snapshots(&backend(), user_id).await.unwrap());
+    let snapshots = history::snapshots(&*common::store(), user_id, TimeRange::LongTerm).unwrap();
+    assert_eq!(snapshots.last().unwrap().date, days_ago(0));
+    assert_eq!(mock.calls("cached-history-user", "/v1/me/top/artists"), 4);
+}
+
+#[tokio::test]
+async fn users_whose_refresh_token_is_revoked_are_not_snapshotted() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("revoked-history-user").await;
+
+    let user_id = "spotify:user:revoked-history-user";
+    put_snapshot(&*common::store(), user_id, TimeRange::ShortTerm, 1, vec![artist(1, "The Orbiters")]);
+    expire_token(user_id);
+
+    assert!(!history::take_user_snapshots(&backend(), user_id).await.unwrap());
+
+    assert_eq!(mock.calls("revoked-history-user", "/v1/me/top/artists"), 0);
+    let snapshots = history::snapshots(&*common::store(), user_id, TimeRange::ShortTerm).unwrap();
+    assert_eq!(snapshots.last().unwrap().date, days_ago(1));
+}
+
+#[tokio::test]
+async fn rank_movement_rejects_periods_before_the_calendar() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("far-back-history-user").await;
+
+    let response = client.server_fn::<GetRankMovement>("range=short_term&days=4294967295").await;
+
+    assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(response.body.contains("Can't look back 4294967295 days"), "{}", response.body);
+}
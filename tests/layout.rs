@@ -0,0 +1,68 @@
+use starify::constellation::{
+    layout::{self, LayoutOptions},
+    ArtistNode, Constellation, Edge, EdgeKind,
+};
+
+fn node(rank: usize, name: &str, genres: &[&str], popularity: u32) -> ArtistNode {
+    ArtistNode {
+        id: format!("spotify:artist:{rank:022}"),
+        name: name.to_string(),
+        genres: genres.iter().map(|genre| genre.to_string()).collect(),
+        image: None,
+        popularity,
+        rank,
+    }
+}
+
+fn edge(source: usize, target: usize, weight: f32, kind: EdgeKind) -> Edge {
+    Edge { source, target, weight, kind }
+}
+
+fn constellation() -> Constellation {
+    Constellation {
+        nodes: vec![
+            node(0, "The Orbiters", &["space rock"], 80),
+            node(1, "Nebula Drive", &["space rock", "synthwave"], 65),
+            node(2, "Quiet Comet", &["ambient"], 40),
+            node(3, "Faded Star", &["ambient"], 10),
+            node(4, "Red Giant", &["synthwave"], 55),
+        ],
+        edges: vec![
+            edge(0, 1, 1.0, EdgeKind::Related),
+            edge(0, 1, 0.5, EdgeKind::SharedGenre),
+            edge(1, 4, 0.5, EdgeKind::SharedGenre),
+            edge(2, 3, 0.5, EdgeKind::SharedGenre),
+            edge(3, 4, 0.25, EdgeKind::SharedTrack),
+        ],
+    }
+}
+
+#[test]
+fn layouts_are_deterministic() {
+    let options = LayoutOptions::default();
+
+    let first = layout::layout(&constellation(), &options);
+    let second = layout::layout(&constellation(), &options);
+
+    assert_eq!(first.len(), 5);
+    // compare the exact bits, so even the smallest drift shows up
+    let bits = |points: &[layout::Point]| -> Vec<(u64, u64)> {
+        points.iter().map(|point| (point.x.to_bits(), point.y.to_bits())).collect()
+    };
+    assert_eq!(bits(&first), bits(&second));
+
+    for point in &first {
+        assert!((0.0..=options.width).contains(&point.x) && (0.0..=options.height).contains(&point.y));
+    }
+}
+
+#[test]
+fn layouts_depend_on_the_seed() {
+    let options = LayoutOptions::default();
+    let reseeded = LayoutOptions { seed: options.seed + 1, ..options.clone() };
+
+    assert_ne!(
+        layout::layout(&constellation(), &options),
+        layout::layout(&constellation(), &reseeded)
+    );
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rspotify::{model::FullArtist, Token};
+use sha2::{Digest, Sha256};
+
+use common::TestClient;
+use starify::{
+    app::{Login, LoginInfo},
+    auth::tokens,
+    client::GetTopArtists,
+    config::AuthFlow,
+};
+
+#[tokio::test]
+async fn logins_send_a_verifier_matching_the_challenge() {
+    let mock = common::setup_with_flow(AuthFlow::Pkce);
+    let mut client = TestClient::new();
+
+    let info = client.server_fn::<Login>("").await;
+    let info: LoginInfo = serde_json::from_str(&info.body).unwrap();
+
+    assert_eq!(common::query_param(&info.url, "code_challenge_method").as_deref(), Some("S256"));
+    let challenge = common::query_param(&info.url, "code_challenge").unwrap();
+    let state = common::query_param(&info.url, "state").unwrap();
+
+    let response = client.get(&format!("/authorize?code=pkce-user&state={state}")).await;
+    assert_eq!(response.location.as_deref(), Some("/dashboard"));
+
+    let verifier = mock.verifier("pkce-user").expect("a code verifier");
+    assert_eq!(URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())), challenge);
+}
+
+#[tokio::test]
+async fn every_login_gets_its_own_verifier() {
+    let mock = common::setup_with_flow(AuthFlow::Pkce);
+
+    let mut first = TestClient::new();
+    first.login("pkce-first").await;
+
+    let mut second = TestClient::new();
+    second.login("pkce-second").await;
+
+    assert_ne!(mock.verifier("pkce-first"), mock.verifier("pkce-second"));
+}
+
+#[tokio::test]
+async fn tokens_are_refreshed_without_a_secret() {
+    let mock = common::setup_with_flow(AuthFlow::Pkce);
+    let mut client = TestClient::new();
+    client.login("pkce-refresh-user").await;
+
+    let key = "spotify:user:pkce-refresh-user";
+    let mut token: Token = tokens::get_token(&*common::store(), key).unwrap().expect("stored token");
+    token.expires_at = Some(chrono::Utc::now() - chrono::Duration::minutes(1));
+    tokens::put_token(&*common::store(), key, &token).unwrap();
+
+    let response = client.server_fn::<GetTopArtists>("range=short_term").await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let artists: Option<Vec<FullArtist>> = serde_json::from_str(&response.body).unwrap();
+    assert_eq!(artists.map(|artists| artists.len()), Some(3));
+    assert_eq!(mock.calls("pkce-refresh-user", "/api/token"), 2);
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use axum::http::StatusCode;
+use futures::future::join_all;
+
+use common::TestClient;
+use starify::client::{GetTopArtists, RefreshData};
+
+#[tokio::test]
+async fn concurrent_refreshes_claim_the_cooldown_once() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("eager-user").await;
+
+    let responses = join_all((0..4).map(|_| {
+        let mut client = client.clone();
+        async move { client.server_fn::<RefreshData>("").await }
+    }))
+    .await;
+
+    let refreshed = responses.iter().filter(|response| response.status == StatusCode::OK).count();
+
+    assert_eq!(refreshed, 1);
+    assert_eq!(mock.calls("eager-user", "/v1/me/top/artists"), 3);
+}
+
+#[tokio::test]
+async fn refreshing_keeps_the_cache_of_users_whose_id_extends_it() {
+    let mock = common::setup();
+
+    let mut ann = TestClient::new();
+    ann.login("ann").await;
+
+    let mut ann_lee = TestClient::new();
+    ann_lee.login("ann_lee").await;
+    ann_lee.server_fn::<GetTopArtists>("range=short_term").await;
+
+    let response = ann.server_fn::<RefreshData>("").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    ann_lee.server_fn::<GetTopArtists>("range=short_term").await;
+    assert_eq!(mock.calls("ann_lee", "/v1/me/top/artists"), 1);
+}
@@ -0,0 +1,155 @@
+mod common;
+
+use std::sync::{Arc, OnceLock};
+
+use axum_login::tower_sessions::{Session, SessionStore};
+use rspotify::model::{FullArtist, TimeRange};
+
+use common::TestClient;
+use starify::{
+    client::{self, GetTopArtists, CACHE_TREE},
+    history::{self, HistoryArtist, HistorySnapshot, HISTORY_TREE},
+    session::{Sessions, SESSIONS_TREE},
+    store::{
+        migrations::{self, TreeChanges, SCHEMA_VERSION},
+        schema, MemoryStore, SharedStore, Store, DEFAULT_TREE,
+    },
+};
+
+fn legacy_snapshot(date: &str) -> HistorySnapshot {
+    HistorySnapshot {
+        date: date.to_string(),
+        taken_at: 0,
+        artists: vec![HistoryArtist {
+            id: "spotify:artist:0000000000000000000001".to_string(),
+            name: "The Orbiters".to_string(),
+            image: None,
+        }],
+    }
+}
+
+/// A session someone logged in with before values were tagged.
+fn legacy_session() -> Session {
+    static SESSION: OnceLock<Session> = OnceLock::new();
+
+    SESSION
+        .get_or_init(|| {
+            let session = Session::new(None);
+            session.insert("user", "spotify:user:old").unwrap();
+            session
+        })
+        .clone()
+}
+
+/// A store written before values were tagged.
+fn legacy_store() -> MemoryStore {
+    let store = MemoryStore::new();
+    let put = |tree, key, value| store.put(tree, key, value, None).unwrap();
+
+    put(DEFAULT_TREE, "spotify:user:old", b"sealed token".to_vec());
+    put(DEFAULT_TREE, "spotify:user:old_refreshed", bincode::serialize(&1_700_000_000_i64).unwrap());
+    put(HISTORY_TREE, "spotify:user:old_short_2024-01-01", bincode::serialize(&legacy_snapshot("2024-01-01")).unwrap());
+    put(HISTORY_TREE, "spotify:user:old_short_2024-01-02", b"\x01".to_vec());
+    put(CACHE_TREE, "spotify:user:old_userinfo", b"cached profile".to_vec());
+    // cached before responses had their own tree
+    put(DEFAULT_TREE, "spotify:user:old_userinfo", b"cached profile".to_vec());
+    put(DEFAULT_TREE, "spotify:user:old_topartists_LongTerm", b"cached artists".to_vec());
+    put(DEFAULT_TREE, "spotify:user:old_constellation_ShortTerm", b"cached constellation".to_vec());
+    // the token of someone whose ID only looks like a cache key
+    put(DEFAULT_TREE, "spotify:user:fan_userinfo", b"STK1 sealed token".to_vec());
+    // sessions were bincode of their expiry date and JSON
+    let session = legacy_session();
+    let expiry_date = time::OffsetDateTime::now_utc().unix_timestamp() + 60 * 60;
+    let record = bincode::serialize(&(expiry_date, serde_json::to_vec(&session).unwrap())).unwrap();
+    put(SESSIONS_TREE, &session.id().0.to_string(), record);
+
+    store
+}
+
+#[tokio::test]
+async fn dry_runs_report_without_changing_anything() {
+    let store = legacy_store();
+    let before = store.scan_prefix(HISTORY_TREE, "").unwrap();
+
+    let reports = migrations::migrate(&store, true).unwrap();
+
+    assert_eq!(reports.iter().map(|report| report.version).collect::<Vec<_>>(), (1..=SCHEMA_VERSION).collect::<Vec<_>>());
+    assert_eq!(reports[0].changes[HISTORY_TREE], TreeChanges { rewritten: 1, removed: 1 });
+    assert_eq!(reports[0].changes[CACHE_TREE], TreeChanges { rewritten: 0, removed: 1 });
+    assert_eq!(reports[0].changes[DEFAULT_TREE], TreeChanges { rewritten: 1, removed: 0 });
+    assert_eq!(reports[0].changes[SESSIONS_TREE], TreeChanges { rewritten: 1, removed: 0 });
+    assert_eq!(reports[1].changes[DEFAULT_TREE], TreeChanges { rewritten: 0, removed: 3 });
+
+    assert_eq!(store.scan_prefix(HISTORY_TREE, "").unwrap(), before);
+    assert_eq!(migrations::schema_version(&store).unwrap(), 0);
+}
+
+#[tokio::test]
+async fn dry_runs_report_what_migrating_does() {
+    let dry_run = migrations::migrate(&legacy_store(), true).unwrap();
+    let real_run = migrations::migrate(&legacy_store(), false).unwrap();
+
+    assert_eq!(dry_run, real_run);
+}
+
+#[tokio::test]
+async fn migrations_tag_legacy_values() {
+    let store = Arc::new(legacy_store());
+
+    migrations::migrate(&*store, false).unwrap();
+
+    assert_eq!(migrations::schema_version(&*store).unwrap(), SCHEMA_VERSION);
+    assert_eq!(history::snapshots(&*store, "spotify:user:old", TimeRange::ShortTerm).unwrap(), [
+        legacy_snapshot("2024-01-01")
+    ]);
+    assert_eq!(client::get_from_db::<i64>(&*store, "spotify:user:old/refreshed").await.unwrap(), Some(1_700_000_000));
+    assert_eq!(store.get(CACHE_TREE, "spotify:user:old_userinfo").unwrap(), None);
+
+    // sessions are kept, so nobody is logged out
+    let session = legacy_session();
+    let loaded = Sessions::new(store.clone() as SharedStore).load(session.id()).await.unwrap().unwrap();
+    assert_eq!(loaded.get::<String>("user").unwrap().as_deref(), Some("spotify:user:old"));
+
+    // tokens keep their own format
+    assert_eq!(store.get(DEFAULT_TREE, "spotify:user:old").unwrap().as_deref(), Some(&b"sealed token"[..]));
+    assert!(store.get(DEFAULT_TREE, "spotify:user:fan_userinfo").unwrap().is_some());
+
+    for key in ["spotify:user:old_userinfo", "spotify:user:old_topartists_LongTerm", "spotify:user:old_constellation_ShortTerm"] {
+        assert_eq!(store.get(DEFAULT_TREE, key).unwrap(), None, "{key}");
+    }
+
+    assert!(migrations::migrate(&*store, false).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn stores_of_newer_releases_are_refused() {
+    let store = MemoryStore::new();
+    store.put(migrations::META_TREE, "schema_version", (SCHEMA_VERSION + 1).to_be_bytes().to_vec(), None).unwrap();
+
+    assert!(migrations::migrate(&store, true).is_err());
+}
+
+#[tokio::test]
+async fn unreadable_values_are_cache_misses() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("corrupt-cache-user").await;
+
+    let store = common::store();
+    let short_key = "spotify:user:corrupt-cache-user/topartists_ShortTerm";
+    let long_key = "spotify:user:corrupt-cache-user/topartists_LongTerm";
+
+    // garbage, and a readable value of the wrong type
+    store.put(CACHE_TREE, short_key, b"not bincode".to_vec(), None).unwrap();
+    store.put(CACHE_TREE, long_key, schema::encode(&42_i64), None).unwrap();
+
+    for range in ["short_term", "long_term"] {
+        let response = client.server_fn::<GetTopArtists>(&format!("range={range}")).await;
+        let artists: Option<Vec<FullArtist>> = serde_json::from_str(&response.body).unwrap();
+
+        assert_eq!(artists.map(|artists| artists.len()), Some(3));
+    }
+
+    assert_eq!(mock.calls("corrupt-cache-user", "/v1/me/top/artists"), 2);
+    assert!(client::get_from_cache::<Vec<FullArtist>>(&*store, short_key).await.unwrap().is_some());
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use axum::http::StatusCode;
+
+use common::TestClient;
+use rspotify::model::TimeRange;
+use starify::share::{GetMySnapshots, GetSnapshot, RevokeSnapshot, ShareConstellation, Snapshot};
+
+async fn share(client: &mut TestClient, args: &str) -> String {
+    let response = client.server_fn::<ShareConstellation>(args).await;
+    let id: Option<String> = serde_json::from_str(&response.body).unwrap();
+
+    id.unwrap()
+}
+
+async fn snapshot(client: &mut TestClient, id: &str) -> Option<Snapshot> {
+    let response = client.server_fn::<GetSnapshot>(&format!("id={id}")).await;
+
+    serde_json::from_str(&response.body).unwrap()
+}
+
+#[tokio::test]
+async fn snapshots_are_public_and_only_expose_what_was_chosen() {
+    common::setup();
+    let mut owner = TestClient::new();
+    owner.login("share-user").await;
+
+    let named = share(&mut owner, "range=short_term&options[show_name]=true&options[show_range]=false").await;
+    let anonymous = share(&mut owner, "range=long_term&options[show_name]=false&options[show_range]=true").await;
+
+    let mut visitor = TestClient::new();
+
+    let named = snapshot(&mut visitor, &named).await.unwrap();
+    assert_eq!(named.display_name.as_deref(), Some("share-user"));
+    assert_eq!(named.range, None);
+    assert_eq!(named.constellation.nodes.len(), 3);
+
+    let anonymous = snapshot(&mut visitor, &anonymous).await.unwrap();
+    assert_eq!(anonymous.display_name, None);
+    assert_eq!(anonymous.range, Some(TimeRange::LongTerm));
+
+    let page = visitor.get(&format!("/s/{}", anonymous.id)).await;
+    assert_eq!(page.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn only_the_owner_can_revoke_a_snapshot() {
+    common::setup();
+    let mut owner = TestClient::new();
+    owner.login("revoke-owner").await;
+
+    let id = share(&mut owner, "range=medium_term&options[show_name]=false&options[show_range]=true").await;
+
+    let mut other = TestClient::new();
+    other.login("revoke-other").await;
+
+    let response = other.server_fn::<RevokeSnapshot>(&format!("id={id}")).await;
+    assert_eq!(response.status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(snapshot(&mut other, &id).await.is_some());
+
+    let response = owner.server_fn::<RevokeSnapshot>(&format!("id={id}")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(snapshot(&mut other, &id).await.is_none());
+
+    let mine = owner.server_fn::<GetMySnapshots>("").await;
+    let mine: Option<Vec<Snapshot>> = serde_json::from_str(&mine.body).unwrap();
+    assert_eq!(mine, Some(Vec::new()));
+}
@@ -0,0 +1,100 @@
+use time::Duration;
+
+use starify::store::{MemoryStore, SledStore, Store};
+
+/// A fresh directory for a [`SledStore`].
+fn sled_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("starify-store-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+
+    path
+}
+
+fn puts_gets_and_deletes(store: &dyn Store) {
+    store.put("things", "a", b"one".to_vec(), None).unwrap();
+
+    assert_eq!(store.get("things", "a").unwrap().as_deref(), Some(&b"one"[..]));
+    assert_eq!(store.get("other", "a").unwrap(), None);
+
+    store.delete("things", "a").unwrap();
+    assert_eq!(store.get("things", "a").unwrap(), None);
+}
+
+fn scans_by_prefix_in_key_order(store: &dyn Store) {
+    for key in ["user_b", "user_a", "users_c", "user"] {
+        store.put("scan", key, key.as_bytes().to_vec(), None).unwrap();
+    }
+
+    let keys: Vec<String> = store.scan_prefix("scan", "user_").unwrap().into_iter().map(|(key, _)| key).collect();
+
+    assert_eq!(keys, ["user_a", "user_b"]);
+}
+
+fn expires_entries_after_their_ttl(store: &dyn Store) {
+    store.put("ttl", "expired", b"old".to_vec(), Some(Duration::ZERO)).unwrap();
+    store.put("ttl", "fresh", b"new".to_vec(), Some(Duration::hours(1))).unwrap();
+    store.put("ttl", "forever", b"kept".to_vec(), None).unwrap();
+
+    assert_eq!(store.prune_expired().unwrap(), 1);
+    assert_eq!(store.get("ttl", "expired").unwrap(), None);
+    assert_eq!(store.scan_prefix("ttl", "").unwrap().len(), 2);
+
+    // putting again without a TTL keeps the entry for good
+    store.put("ttl", "renewed", b"old".to_vec(), Some(Duration::ZERO)).unwrap();
+    store.put("ttl", "renewed", b"new".to_vec(), None).unwrap();
+
+    assert_eq!(store.get("ttl", "renewed").unwrap().as_deref(), Some(&b"new"[..]));
+}
+
+fn swaps_only_unchanged_values(store: &dyn Store) {
+    assert!(store.compare_and_swap("cas", "key", None, Some(b"first".to_vec())).unwrap());
+    assert!(!store.compare_and_swap("cas", "key", None, Some(b"second".to_vec())).unwrap());
+    assert!(store.compare_and_swap("cas", "key", Some(b"first"), Some(b"third".to_vec())).unwrap());
+
+    assert_eq!(store.get("cas", "key").unwrap().as_deref(), Some(&b"third"[..]));
+
+    // an expired entry counts as missing
+    store.put("cas", "expired", b"old".to_vec(), Some(Duration::ZERO)).unwrap();
+    assert!(store.compare_and_swap("cas", "expired", None, Some(b"new".to_vec())).unwrap());
+}
+
+#[test]
+fn memory_store() {
+    let store = MemoryStore::new();
+
+    puts_gets_and_deletes(&store);
+    scans_by_prefix_in_key_order(&store);
+    expires_entries_after_their_ttl(&store);
+    swaps_only_unchanged_values(&store);
+}
+
+#[test]
+fn sled_store() {
+    let path = sled_path("contract");
+    let store = SledStore::open(&path).unwrap();
+
+    puts_gets_and_deletes(&store);
+    scans_by_prefix_in_key_order(&store);
+    expires_entries_after_their_ttl(&store);
+    swaps_only_unchanged_values(&store);
+
+    drop(store);
+    std::fs::remove_dir_all(&path).unwrap();
+}
+
+#[test]
+fn sled_store_keeps_entries_across_restarts() {
+    let path = sled_path("restart");
+
+    let store = SledStore::open(&path).unwrap();
+    store.put("things", "a", b"one".to_vec(), None).unwrap();
+    store.put("things", "b", b"two".to_vec(), Some(Duration::hours(1))).unwrap();
+    drop(store);
+
+    let store = SledStore::open(&path).unwrap();
+    assert_eq!(store.get("things", "a").unwrap().as_deref(), Some(&b"one"[..]));
+    assert_eq!(store.get("things", "b").unwrap().as_deref(), Some(&b"two"[..]));
+
+    drop(store);
+    std::fs::remove_dir_all(&path).unwrap();
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use rspotify::{model::FullArtist, Token};
+
+use axum::http::StatusCode;
+
+use common::TestClient;
+use starify::{
+    auth::tokens,
+    client::GetTopArtists,
+    store::DEFAULT_TREE,
+};
+
+/// Make the stored token of `user` look expired.
+async fn expire_token(user: &str) {
+    let key = format!("spotify:user:{user}");
+    let mut token: Token = tokens::get_token(&*common::store(), &key).unwrap().expect("stored token");
+
+    token.expires_at = Some(chrono::Utc::now() - chrono::Duration::minutes(1));
+
+    tokens::put_token(&*common::store(), &key, &token).unwrap();
+}
+
+#[tokio::test]
+async fn expired_tokens_are_refreshed() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("expired-user").await;
+
+    expire_token("expired-user").await;
+
+    let response = client.server_fn::<GetTopArtists>("range=long_term").await;
+    let artists: Option<Vec<FullArtist>> = serde_json::from_str(&response.body).unwrap();
+
+    assert_eq!(artists.map(|artists| artists.len()), Some(3));
+    assert_eq!(mock.calls("expired-user", "/api/token"), 2);
+
+    let token: Token = tokens::get_token(&*common::store(), "spotify:user:expired-user").unwrap().unwrap();
+    assert!(!token.is_expired());
+}
+
+#[tokio::test]
+async fn revoked_tokens_log_out() {
+    let mock = common::setup();
+    let mut client = TestClient::new();
+    client.login("revoked-user").await;
+
+    expire_token("revoked-user").await;
+
+    let response = client.server_fn::<GetTopArtists>("range=long_term").await;
+
+    assert_eq!(response.body, "null");
+    assert_eq!(mock.calls("revoked-user", "/v1/me/top/artists"), 0);
+    assert_eq!(common::store().get(DEFAULT_TREE, "spotify:user:revoked-user").unwrap(), None);
+}
+
+#[tokio::test]
+async fn refreshes_failing_for_other_reasons_keep_the_token() {
+    let mock = common::setup();
+
+    // rate limited, and refused because of the app's own credentials
+    for user in ["ratelimited-user", "misconfigured-user"] {
+        let mut client = TestClient::new();
+        client.login(user).await;
+
+        expire_token(user).await;
+
+        let response = client.server_fn::<GetTopArtists>("range=long_term").await;
+
+        assert_ne!(response.status, StatusCode::OK, "{user}");
+        assert_eq!(mock.calls(user, "/v1/me/top/artists"), 0, "{user}");
+        assert!(common::store().get(DEFAULT_TREE, &format!("spotify:user:{user}")).unwrap().is_some(), "{user}");
+    }
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use axum::http::StatusCode;
+use rspotify::Token;
+
+use common::TestClient;
+use starify::{
+    auth::tokens::{self, TokenCipher},
+    client::GetCurrentUser,
+    config::TokenKey,
+    store::DEFAULT_TREE,
+};
+
+fn stored_token(user_id: &str) -> Token {
+    tokens::get_token(&*common::store(), user_id).unwrap().expect("stored token")
+}
+
+fn stored_entry(user_id: &str) -> Vec<u8> {
+    common::store().get(DEFAULT_TREE, user_id).unwrap().expect("stored token")
+}
+
+fn store_entry(user_id: &str, entry: Vec<u8>) {
+    common::store().put(DEFAULT_TREE, user_id, entry, None).unwrap();
+}
+
+#[tokio::test]
+async fn tokens_are_encrypted_at_rest() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("sealed-user").await;
+
+    let user_id = "spotify:user:sealed-user";
+    let entry = stored_entry(user_id);
+
+    let refresh_token = b"refresh-sealed-user";
+    assert!(!entry.windows(refresh_token.len()).any(|window| window == refresh_token));
+    assert_eq!(stored_token(user_id).refresh_token.as_deref(), Some("refresh-sealed-user"));
+}
+
+#[tokio::test]
+async fn tokens_of_rotated_keys_are_sealed_again() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("rotated-user").await;
+
+    let user_id = "spotify:user:rotated-user";
+    let token = stored_token(user_id);
+
+    let old = TokenCipher::new(vec![common::old_token_key()]);
+    store_entry(user_id, old.seal(user_id, &token));
+
+    assert_eq!(stored_token(user_id).access_token, token.access_token);
+
+    let current = TokenCipher::new(vec![common::current_token_key()]);
+    let entry = stored_entry(user_id);
+    assert_eq!(current.open(user_id, &entry).unwrap().0.access_token, token.access_token);
+}
+
+#[tokio::test]
+async fn unencrypted_tokens_are_sealed_on_read() {
+    common::setup();
+    let mut client = TestClient::new();
+    client.login("legacy-token-user").await;
+
+    let user_id = "spotify:user:legacy-token-user";
+    let token = stored_token(user_id);
+    store_entry(user_id, bincode::serialize(&token).unwrap());
+
+    assert_eq!(stored_token(user_id).access_token, token.access_token);
+
+    let current = TokenCipher::new(vec![common::current_token_key()]);
+    let entry = stored_entry(user_id);
+    assert!(current.open(user_id, &entry).is_ok());
+}
+
+#[tokio::test]
+async fn tokens_of_unknown_or_wrong_keys_are_refused() {
+    common::setup();
+
+    let keys = [
+        TokenKey { id: "retired".to_string(), key: [3; 32] },
+        TokenKey { id: common::current_token_key().id, key: [4; 32] },
+    ];
+
+    for (number, key) in keys.into_iter().enumerate() {
+        let user = format!("wrong-key-user-{number}");
+        let user_id = format!("spotify:user:{user}");
+        let mut client = TestClient::new();
+        client.login(&user).await;
+
+        let token = stored_token(&user_id);
+        let entry = TokenCipher::new(vec![key]).seal(&user_id, &token);
+        store_entry(&user_id, entry.clone());
+
+        assert!(tokens::get_token(&*common::store(), &user_id).is_err());
+
+        let me = client.server_fn::<GetCurrentUser>("").await;
+        assert_eq!(me.status, StatusCode::OK);
+        assert_eq!(me.body, "null");
+
+        // the entry is kept in case the key comes back
+        assert_eq!(stored_entry(&user_id), entry);
+    }
+}